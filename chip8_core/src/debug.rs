@@ -0,0 +1,118 @@
+use crate::{Chip8Error, Emu, RAM_SIZE};
+
+// why step_n stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    // all the requested instructions ran
+    Completed,
+    // PC reached a breakpoint, the instruction at that address has not run yet
+    Breakpoint(u16),
+    // the last instruction changed the byte at a watched RAM address
+    Watch(u16),
+    // PC ran off the end of RAM with the Halt policy
+    Halted,
+    Error(Chip8Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StepResult {
+    // number of instructions that actually ran
+    pub executed: usize,
+    pub reason: StopReason,
+}
+
+impl Emu {
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        if !self.breakpoints.contains(&addr) {
+            self.breakpoints.push(addr);
+        }
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.retain(|bp| *bp != addr);
+    }
+
+    pub fn breakpoints(&self) -> &[u16] {
+        &self.breakpoints
+    }
+
+    // stop step_n whenever the byte at addr changes
+    pub fn add_watch(&mut self, addr: u16) {
+        // addresses past the end of RAM can never change, so there is nothing to watch
+        if (addr as usize) < RAM_SIZE && !self.watches.contains(&addr) {
+            self.watches.push(addr);
+        }
+    }
+
+    pub fn remove_watch(&mut self, addr: u16) {
+        self.watches.retain(|w| *w != addr);
+    }
+
+    pub fn watches(&self) -> &[u16] {
+        &self.watches
+    }
+
+    // run up to n instructions, stopping early on a breakpoint, a watch hit, a halt or an error.
+    // the first instruction always runs, so calling step_n again moves past the breakpoint it stopped on
+    pub fn step_n(&mut self, n: usize) -> StepResult {
+        for executed in 0..n {
+            if executed > 0 && self.breakpoints.contains(&self.pc) {
+                return StepResult { executed, reason: StopReason::Breakpoint(self.pc) };
+            }
+            let before: Vec<u8> = self.watches.iter().map(|addr| self.ram[*addr as usize]).collect();
+            if let Err(e) = self.step() {
+                return StepResult { executed, reason: StopReason::Error(e) };
+            }
+            if self.halted {
+                return StepResult { executed, reason: StopReason::Halted };
+            }
+            let hit = self.watches.iter().zip(before).find(|(addr, old)| self.ram[**addr as usize] != *old);
+            if let Some((addr, _)) = hit {
+                return StepResult { executed: executed + 1, reason: StopReason::Watch(*addr) };
+            }
+        }
+        StepResult { executed: n, reason: StopReason::Completed }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // LD V0, 1; LD V1, 2; LD V2, 3; LD V3, 4; LD V4, 5; LD V5, 6
+    const LOADS: [u8; 12] = [0x60, 1, 0x61, 2, 0x62, 3, 0x63, 4, 0x64, 5, 0x65, 6];
+
+    #[test]
+    fn step_n_stops_at_a_breakpoint() {
+        let mut emu = Emu::new();
+        emu.load(&LOADS).unwrap();
+        // the 4th instruction, so three run before the breakpoint is hit
+        emu.add_breakpoint(0x206);
+        let result = emu.step_n(5);
+        assert_eq!(result, StepResult { executed: 3, reason: StopReason::Breakpoint(0x206) });
+        assert_eq!(emu.registers()[..4], [1, 2, 3, 0]);
+        // stepping again moves past it
+        let result = emu.step_n(2);
+        assert_eq!(result, StepResult { executed: 2, reason: StopReason::Completed });
+        assert_eq!(emu.registers()[..5], [1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn step_n_stops_when_a_watched_byte_changes() {
+        let mut emu = Emu::new();
+        // LD V0, 7; LD I, 0x300; LD [I], V0; LD V1, 1
+        emu.load(&[0x60, 7, 0xA3, 0x00, 0xF0, 0x55, 0x61, 1]).unwrap();
+        emu.add_watch(0x300);
+        let result = emu.step_n(10);
+        assert_eq!(result, StepResult { executed: 3, reason: StopReason::Watch(0x300) });
+    }
+
+    #[test]
+    fn step_n_stops_on_errors() {
+        let mut emu = Emu::new();
+        // LD V0, 1; RET
+        emu.load(&[0x60, 1, 0x00, 0xEE]).unwrap();
+        let result = emu.step_n(5);
+        assert_eq!(result, StepResult { executed: 1, reason: StopReason::Error(Chip8Error::StackUnderflow) });
+    }
+}
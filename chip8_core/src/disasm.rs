@@ -0,0 +1,109 @@
+use std::fmt;
+
+use crate::{Op, RAM_SIZE, START_ADDR};
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Op::Nop => write!(f, "NOP"),
+            Op::ClearScreen => write!(f, "CLS"),
+            Op::Return => write!(f, "RET"),
+            Op::ScrollDown(n) => write!(f, "SCD 0x{:X}", n),
+            Op::ScrollRight => write!(f, "SCR"),
+            Op::ScrollLeft => write!(f, "SCL"),
+            Op::LowRes => write!(f, "LOW"),
+            Op::HighRes => write!(f, "HIGH"),
+            Op::Jump(addr) => write!(f, "JMP 0x{:03X}", addr),
+            Op::Call(addr) => write!(f, "CALL 0x{:03X}", addr),
+            Op::SkipEqImm { x, nn } => write!(f, "SE V{:X}, 0x{:02X}", x, nn),
+            Op::SkipNeImm { x, nn } => write!(f, "SNE V{:X}, 0x{:02X}", x, nn),
+            Op::SkipEqReg { x, y } => write!(f, "SE V{:X}, V{:X}", x, y),
+            Op::LoadImm { x, nn } => write!(f, "LD V{:X}, 0x{:02X}", x, nn),
+            Op::AddImm { x, nn } => write!(f, "ADD V{:X}, 0x{:02X}", x, nn),
+            Op::Move { x, y } => write!(f, "LD V{:X}, V{:X}", x, y),
+            Op::Or { x, y } => write!(f, "OR V{:X}, V{:X}", x, y),
+            Op::And { x, y } => write!(f, "AND V{:X}, V{:X}", x, y),
+            Op::Xor { x, y } => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Op::AddReg { x, y } => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Op::SubReg { x, y } => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Op::ShiftRight { x, y } => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Op::SubNeg { x, y } => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Op::ShiftLeft { x, y } => write!(f, "SHL V{:X}, V{:X}", x, y),
+            Op::SkipNeReg { x, y } => write!(f, "SNE V{:X}, V{:X}", x, y),
+            Op::LoadI(addr) => write!(f, "LD I, 0x{:03X}", addr),
+            Op::JumpV0(addr) => write!(f, "JMP V0, 0x{:03X}", addr),
+            Op::Random { x, nn } => write!(f, "RND V{:X}, 0x{:02X}", x, nn),
+            Op::DrawSprite { x, y, n } => write!(f, "DRW V{:X}, V{:X}, 0x{:X}", x, y, n),
+            Op::SkipKeyPressed { x } => write!(f, "SKP V{:X}", x),
+            Op::SkipKeyNotPressed { x } => write!(f, "SKNP V{:X}", x),
+            Op::LoadDelay { x } => write!(f, "LD V{:X}, DT", x),
+            Op::WaitKey { x } => write!(f, "LD V{:X}, K", x),
+            Op::SetDelay { x } => write!(f, "LD DT, V{:X}", x),
+            Op::SetSound { x } => write!(f, "LD ST, V{:X}", x),
+            Op::AddI { x } => write!(f, "ADD I, V{:X}", x),
+            Op::LoadFont { x } => write!(f, "LD F, V{:X}", x),
+            Op::Bcd { x } => write!(f, "LD B, V{:X}", x),
+            Op::StoreRegs { x } => write!(f, "LD [I], V{:X}", x),
+            Op::LoadRegs { x } => write!(f, "LD V{:X}, [I]", x),
+            // raw data, the word is emitted as is
+            Op::Unknown(op) => write!(f, "DB 0x{:04X}", op),
+        }
+    }
+}
+
+// walk a ROM two bytes at a time as if it was loaded at START_ADDR and turn every word into a mnemonic,
+// sprite data and anything else that isn't an instruction comes out as DB. Bytes past the end of
+// RAM could never be loaded, so they are left out
+pub fn disassemble(rom: &[u8]) -> Vec<(u16, String)> {
+    let loadable = &rom[..rom.len().min(RAM_SIZE - START_ADDR as usize)];
+    loadable.chunks(2)
+        .enumerate()
+        .map(|(i, word)| {
+            let addr = START_ADDR + (i * 2) as u16;
+            let text = match *word {
+                [high, low] => Op::decode(((high as u16) << 8) | low as u16).to_string(),
+                // a ROM with an odd length leaves a single byte at the end
+                [byte] => format!("DB 0x{:02X}", byte),
+                _ => unreachable!(),
+            };
+            (addr, text)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disassembles_a_small_program() {
+        let rom = [
+            0x12, 0xA0, // JMP 0x2A0
+            0x63, 0x1F, // LD V3, 0x1F
+            0xA2, 0x0A, // LD I, 0x20A
+            0xD3, 0x45, // DRW V3, V4, 5
+            0xF3, 0x33, // LD B, V3
+            0x00, 0xEE, // RET
+            0xFF, 0xFF, // not an instruction
+            0x80, // odd trailing byte
+        ];
+        let lines: Vec<(u16, String)> = vec![
+            (0x200, "JMP 0x2A0".to_string()),
+            (0x202, "LD V3, 0x1F".to_string()),
+            (0x204, "LD I, 0x20A".to_string()),
+            (0x206, "DRW V3, V4, 0x5".to_string()),
+            (0x208, "LD B, V3".to_string()),
+            (0x20A, "RET".to_string()),
+            (0x20C, "DB 0xFFFF".to_string()),
+            (0x20E, "DB 0x80".to_string()),
+        ];
+        assert_eq!(disassemble(&rom), lines);
+    }
+
+    #[test]
+    fn stops_at_the_end_of_ram() {
+        let lines = disassemble(&vec![0u8; 70000]);
+        assert_eq!(lines.len(), (RAM_SIZE - START_ADDR as usize) / 2);
+        assert_eq!(lines.last().unwrap().0, (RAM_SIZE - 2) as u16);
+    }
+}
@@ -0,0 +1,113 @@
+use crate::Emu;
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// standard base64 (RFC 4648) with '=' padding
+fn base64_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        // pack up to 3 bytes into 24 bits and split them into four 6 bit indices
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = ((b[0] as u32) << 16) | ((b[1] as u32) << 8) | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_ALPHABET[((n >> (18 - 6 * i)) & 0x3F) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+// inverse of base64_encode, None if the text isn't padded base64. Only the round-trip tests need it,
+// the core isn't meant to be a general base64 library
+#[cfg(test)]
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let text = text.as_bytes();
+    if !text.len().is_multiple_of(4) {
+        return None;
+    }
+    let mut out = Vec::with_capacity(text.len() / 4 * 3);
+    for (i, chunk) in text.chunks(4).enumerate() {
+        let last = i == text.len() / 4 - 1;
+        let padding = chunk.iter().rev().take_while(|c| **c == b'=').count();
+        // padding only ever shows up at the very end, and at most two characters of it
+        if padding > 2 || (padding > 0 && !last) {
+            return None;
+        }
+        let mut n = 0u32;
+        for c in &chunk[..4 - padding] {
+            let index = BASE64_ALPHABET.iter().position(|a| a == c)?;
+            n = (n << 6) | index as u32;
+        }
+        n <<= 6 * padding;
+        out.extend_from_slice(&[(n >> 16) as u8, (n >> 8) as u8, n as u8][..3 - padding]);
+    }
+    Some(out)
+}
+
+// inverse of display_packed, one bool per pixel in the same order as get_display
+pub fn unpack_display(packed: &[u8]) -> Vec<bool> {
+    packed
+        .iter()
+        .flat_map(|byte| (0..8).map(move |i| byte & (0b1000_0000 >> i) != 0))
+        .collect()
+}
+
+impl Emu {
+    // the display as bits, 8 pixels per byte, row by row from the top left,
+    // the leftmost pixel of each group of 8 is the most significant bit (same layout as sprite rows)
+    pub fn display_packed(&self) -> Vec<u8> {
+        self.get_display()
+            .chunks(8)
+            .map(|pixels| {
+                pixels.iter().enumerate().fold(0, |byte, (i, on)| byte | ((*on as u8) << (7 - i)))
+            })
+            .collect()
+    }
+
+    // display_packed encoded as standard base64, handy for embedding frames in HTML/JSON or compact logs
+    // (the standard 64x32 screen is 256 bytes, so 344 characters, hires is 1024 bytes)
+    pub fn display_base64(&self) -> String {
+        base64_encode(&self.display_packed())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64_matches_the_rfc_examples() {
+        let examples = [("", ""), ("f", "Zg=="), ("fo", "Zm8="), ("foo", "Zm9v"), ("foobar", "Zm9vYmFy")];
+        for (plain, encoded) in examples {
+            assert_eq!(base64_encode(plain.as_bytes()), encoded);
+            assert_eq!(base64_decode(encoded), Some(plain.as_bytes().to_vec()));
+        }
+        assert_eq!(base64_decode("Zg="), None);
+        assert_eq!(base64_decode("Z==="), None);
+        assert_eq!(base64_decode("Zg==Zm8="), None);
+        assert_eq!(base64_decode("Zm9*"), None);
+    }
+
+    #[test]
+    fn display_round_trips_through_base64() {
+        let mut emu = Emu::new();
+        // LD V0, 3; LD V1, 7; LD F, V1; DRW V0, V1, 5
+        emu.load(&[0x60, 3, 0x61, 7, 0xF1, 0x29, 0xD0, 0x15]).unwrap();
+        for _ in 0..4 {
+            emu.tick().unwrap();
+        }
+        let packed = emu.display_packed();
+        assert_eq!(packed.len(), 256);
+        // the top row of the "7" glyph, 0xF0 shifted right by 3 pixels
+        assert_eq!(packed[7 * 8], 0b0001_1110);
+
+        let text = emu.display_base64();
+        assert_eq!(text.len(), 344);
+        let decoded = base64_decode(&text).unwrap();
+        assert_eq!(decoded, packed);
+        assert_eq!(unpack_display(&decoded), emu.get_display());
+    }
+}
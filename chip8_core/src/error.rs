@@ -0,0 +1,50 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chip8Error {
+    // the ROM doesn't fit in RAM after START_ADDR
+    RomTooLarge { size: usize, max: usize },
+    // PC ran past the end of RAM, so there is no full instruction left to fetch
+    PcOutOfBounds(u16),
+    // the word at PC isn't an instruction this core implements (0NNN machine code calls, data run as code)
+    UnknownOpcode(u16),
+    // an instruction tried to read or write RAM past the end, the address is where the access started
+    AddressOutOfBounds(u16),
+    // CALL with all STACK_SIZE slots already in use
+    StackOverflow,
+    // RET with nothing on the stack
+    StackUnderflow,
+    // a store into the fontset while font protection is on
+    FontWrite(u16),
+    // the save state doesn't start with the expected tag, so it isn't one
+    BadStateMagic,
+    // the save state was written by a format version this core can't read
+    UnsupportedStateVersion(u8),
+    BadStateLength { expected: usize, found: usize },
+    // the save state has the right shape but holds values the machine can't be in
+    CorruptState,
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Chip8Error::RomTooLarge { size, max } => {
+                write!(f, "ROM is {} bytes, but at most {} bytes fit in memory", size, max)
+            },
+            Chip8Error::PcOutOfBounds(pc) => write!(f, "program counter {:#05X} is past the end of RAM", pc),
+            Chip8Error::UnknownOpcode(op) => write!(f, "unknown opcode {:#06X}", op),
+            Chip8Error::AddressOutOfBounds(addr) => write!(f, "memory access at {:#05X} runs past the end of RAM", addr),
+            Chip8Error::StackOverflow => write!(f, "stack overflow, too many nested calls"),
+            Chip8Error::StackUnderflow => write!(f, "stack underflow, return without a matching call"),
+            Chip8Error::FontWrite(addr) => write!(f, "write to protected font memory at {:#05X}", addr),
+            Chip8Error::BadStateMagic => write!(f, "not a save state"),
+            Chip8Error::UnsupportedStateVersion(v) => write!(f, "unsupported save state version {}", v),
+            Chip8Error::BadStateLength { expected, found } => {
+                write!(f, "save state is {} bytes, expected {}", found, expected)
+            },
+            Chip8Error::CorruptState => write!(f, "save state is corrupt"),
+        }
+    }
+}
+
+impl std::error::Error for Chip8Error {}
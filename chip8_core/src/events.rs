@@ -0,0 +1,149 @@
+use crate::{Chip8Error, Emu, Op};
+
+// things that happen inside the core which tools (loggers, recorders, debuggers) may want to react to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmuEvent {
+    // an instruction finished executing, pc is the address it was fetched from
+    OpcodeExecuted { pc: u16, op: u16 },
+    ScreenCleared,
+    SpriteDrawn { collided: bool },
+    // the sound timer went from zero to a nonzero value
+    BeepStarted,
+    // a return address was pushed by CALL
+    StackPushed(u16),
+    // an instruction wrote VF, either as a carry/borrow/shift/collision flag or as its target register
+    VfWritten { cause: Op, value: u8 },
+    Error(Chip8Error),
+}
+
+// Send so an emulator with subscribers can still be moved to a worker thread
+pub type EventCallback = Box<dyn FnMut(&EmuEvent) + Send>;
+
+impl Emu {
+    // register a callback that receives every event from now on
+    pub fn subscribe<F>(&mut self, callback: F)
+    where
+        F: FnMut(&EmuEvent) + Send + 'static,
+    {
+        self.subscribers.push(Box::new(callback));
+    }
+
+    pub(crate) fn emit(&mut self, event: EmuEvent) {
+        // with no subscribers this is just an empty loop, so the core doesn't pay for events nobody listens to
+        for callback in self.subscribers.iter_mut() {
+            callback(&event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    fn record_events(emu: &mut Emu) -> Arc<Mutex<Vec<EmuEvent>>> {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&events);
+        emu.subscribe(move |event| sink.lock().unwrap().push(*event));
+        events
+    }
+
+    #[test]
+    fn events_fire_for_a_short_rom() {
+        let mut emu = Emu::new();
+        emu.load(&[
+            0x00, 0xE0, // 0x200 CLS
+            0x22, 0x08, // 0x202 CALL 0x208
+            0x12, 0x04, // 0x204 JMP 0x204
+            0x00, 0x00,
+            0x60, 0x05, // 0x208 LD V0, 5
+            0xF0, 0x18, // 0x20A LD ST, V0
+            0xD0, 0x01, // 0x20C DRW V0, V0, 1
+            0x00, 0xEE, // 0x20E RET
+        ])
+        .unwrap();
+        let events = record_events(&mut emu);
+        for _ in 0..6 {
+            emu.tick().unwrap();
+        }
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                EmuEvent::ScreenCleared,
+                EmuEvent::OpcodeExecuted { pc: 0x200, op: 0x00E0 },
+                EmuEvent::StackPushed(0x204),
+                EmuEvent::OpcodeExecuted { pc: 0x202, op: 0x2208 },
+                EmuEvent::OpcodeExecuted { pc: 0x208, op: 0x6005 },
+                EmuEvent::BeepStarted,
+                EmuEvent::OpcodeExecuted { pc: 0x20A, op: 0xF018 },
+                EmuEvent::VfWritten { cause: Op::DrawSprite { x: 0, y: 0, n: 1 }, value: 0 },
+                EmuEvent::SpriteDrawn { collided: false },
+                EmuEvent::OpcodeExecuted { pc: 0x20C, op: 0xD001 },
+                EmuEvent::OpcodeExecuted { pc: 0x20E, op: 0x00EE },
+            ]
+        );
+    }
+
+    #[test]
+    fn errors_are_reported_as_events() {
+        let mut emu = Emu::new();
+        // RET with nothing on the stack
+        emu.load(&[0x00, 0xEE]).unwrap();
+        let events = record_events(&mut emu);
+        assert_eq!(emu.tick(), Err(Chip8Error::StackUnderflow));
+        assert_eq!(*events.lock().unwrap(), vec![EmuEvent::Error(Chip8Error::StackUnderflow)]);
+    }
+
+    fn vf_writes(emu: &mut Emu, ticks: usize) -> Vec<EmuEvent> {
+        let events = record_events(emu);
+        for _ in 0..ticks {
+            emu.tick().unwrap();
+        }
+        let writes = events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|event| matches!(event, EmuEvent::VfWritten { .. }))
+            .copied()
+            .collect();
+        writes
+    }
+
+    #[test]
+    fn carry_from_8xy4_is_logged_as_a_vf_write() {
+        let mut emu = Emu::new();
+        // LD V0, 0xFF; LD V1, 2; ADD V0, V1
+        emu.load(&[0x60, 0xFF, 0x61, 0x02, 0x80, 0x14]).unwrap();
+        assert_eq!(
+            vf_writes(&mut emu, 3),
+            vec![EmuEvent::VfWritten { cause: Op::AddReg { x: 0, y: 1 }, value: 1 }]
+        );
+        assert_eq!(emu.registers()[0], 1);
+    }
+
+    #[test]
+    fn loading_vf_directly_is_logged() {
+        let mut emu = Emu::new();
+        // LD VF, 5; OR VF, V0; ADD VF, 1
+        emu.load(&[0x6F, 0x05, 0x8F, 0x01, 0x7F, 0x01]).unwrap();
+        assert_eq!(vf_writes(&mut emu, 3), vec![
+            EmuEvent::VfWritten { cause: Op::LoadImm { x: 0xF, nn: 5 }, value: 5 },
+            EmuEvent::VfWritten { cause: Op::Or { x: 0xF, y: 0 }, value: 5 },
+            EmuEvent::VfWritten { cause: Op::AddImm { x: 0xF, nn: 1 }, value: 6 },
+        ]);
+        assert_eq!(emu.registers()[0xF], 6);
+    }
+
+    #[test]
+    fn fx65_into_vf_is_logged() {
+        let mut emu = Emu::new();
+        // LD I, 0x206; LD VF, [I]; 16 bytes of register values follow
+        let mut rom = vec![0xA2, 0x06, 0xFF, 0x65, 0x00, 0x00];
+        rom.extend(0..16u8);
+        emu.load(&rom).unwrap();
+        assert_eq!(vf_writes(&mut emu, 2), vec![
+            EmuEvent::VfWritten { cause: Op::LoadRegs { x: 0xF }, value: 15 },
+        ]);
+    }
+}
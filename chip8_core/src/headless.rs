@@ -0,0 +1,364 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{Chip8Error, Emu, RAM_SIZE, TIMER_HZ};
+
+// source of time for timed runs, tests can pass a fake clock that jumps forward when asked to sleep
+pub trait Clock {
+    // time elapsed since the clock was created
+    fn now(&self) -> Duration;
+    fn sleep(&mut self, duration: Duration);
+}
+
+// wall clock time
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        Self { start: Instant::now() }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+// a key press or release that happens right before the given cycle is executed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputEvent {
+    pub cycle: usize,
+    pub key: usize,
+    pub pressed: bool,
+}
+
+// scripted input for headless runs, events are kept sorted by cycle
+#[derive(Debug, Clone, Default)]
+pub struct InputSchedule {
+    events: Vec<InputEvent>,
+}
+
+impl InputSchedule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn press(&mut self, cycle: usize, key: usize) {
+        self.add(InputEvent { cycle, key, pressed: true });
+    }
+
+    pub fn release(&mut self, cycle: usize, key: usize) {
+        self.add(InputEvent { cycle, key, pressed: false });
+    }
+
+    pub fn add(&mut self, event: InputEvent) {
+        // insert after any event on the same cycle so events keep the order they were added in
+        let index = self.events.partition_point(|e| e.cycle <= event.cycle);
+        self.events.insert(index, event);
+    }
+
+    pub fn events(&self) -> &[InputEvent] {
+        &self.events
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RunSummary {
+    // number of instructions actually executed
+    pub cycles: usize,
+    // number of pixels that are on at the end of the run
+    pub lit_pixels: usize,
+    // true if the run stopped early because the ROM jumped to itself (the usual "end of program" idiom)
+    // or PC ran off the end of RAM with the Halt policy
+    pub halted: bool,
+    // set if the run stopped because of an error
+    pub error: Option<Chip8Error>,
+    pub state_hash: u64,
+}
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+// clock time at which frame n (counting from 0) is due, worked out in integer nanoseconds so
+// sleeping exactly until then always makes the frame due (floats can land a hair short)
+fn frame_end(frame: u64) -> Duration {
+    let nanos = (frame as u128 + 1) * NANOS_PER_SEC / TIMER_HZ as u128;
+    Duration::new((nanos / NANOS_PER_SEC) as u64, (nanos % NANOS_PER_SEC) as u32)
+}
+
+impl Emu {
+    // run one 60Hz frame: let the input hook update the keys, run `ticks` instructions, tick the timers once.
+    // returns true if the frame cleared or drew to the screen, so callers know when to redraw
+    pub fn run_frame(&mut self, ticks: usize) -> Result<bool, Chip8Error> {
+        self.display_dirty = false;
+        self.begin_frame();
+        for _ in 0..ticks {
+            self.tick()?;
+        }
+        // a FX18 in this frame sounds for at least this frame, even though the tick below may end it
+        self.frame_beeping = self.is_beeping();
+        self.tick_timers();
+        Ok(self.display_dirty)
+    }
+
+    // run for `duration` of clock time with a fixed TIMER_HZ timestep: each frame runs ticks_per_frame()
+    // instructions once the clock says it is due. every frame is emulated the same way no matter how
+    // late it runs, so the result doesn't depend on how fast the host is. CXNN still draws from the RNG,
+    // so runs are only repeatable with a fixed set_rng_seed
+    pub fn run_for(&mut self, duration: Duration, clock: &mut dyn Clock) -> RunSummary {
+        let ticks_per_frame = self.ticks_per_frame();
+        // whole frames, rounded to the nearest one (durations too long to count just run until halted)
+        let total_frames = (duration.as_nanos() * TIMER_HZ as u128 + NANOS_PER_SEC / 2) / NANOS_PER_SEC;
+        let total_frames = u64::try_from(total_frames).unwrap_or(u64::MAX);
+        let start_cycles = self.cycles;
+        let start = clock.now();
+        let mut frames = 0;
+        let mut error = None;
+
+        while frames < total_frames && !self.is_halted() {
+            // catch up on every frame that is due, then wait for the next one
+            let elapsed = clock.now().saturating_sub(start);
+            let due = frame_end(frames);
+            if elapsed >= due {
+                if let Err(e) = self.run_frame(ticks_per_frame) {
+                    error = Some(e);
+                    break;
+                }
+                frames += 1;
+            } else {
+                clock.sleep(due - elapsed);
+            }
+        }
+
+        RunSummary {
+            cycles: self.cycles - start_cycles,
+            lit_pixels: self.get_display().iter().filter(|p| **p).count(),
+            halted: self.is_halted(),
+            error,
+            state_hash: self.state_hash(),
+        }
+    }
+
+    // run up to max_cycles instructions without a frontend, feeding the scripted input
+    // (after the input hook, so the schedule wins if both touch a key)
+    // and ticking the timers every ticks_per_frame() instructions
+    pub fn run_headless(&mut self, max_cycles: usize, inputs: &InputSchedule) -> RunSummary {
+        let ticks_per_frame = self.ticks_per_frame();
+        let mut events = inputs.events().iter().peekable();
+        let mut cycles = 0;
+        let mut halted = false;
+        let mut error = None;
+
+        while cycles < max_cycles {
+            if cycles % ticks_per_frame == 0 {
+                self.begin_frame();
+            }
+            // apply every input scheduled for this cycle (or missed ones from before it)
+            while let Some(event) = events.next_if(|e| e.cycle <= cycles) {
+                self.keypress(event.key, event.pressed);
+            }
+            // a jump to the current address will spin forever, no point in running it
+            if (self.pc as usize) + 1 < RAM_SIZE && self.peek_op() == 0x1000 | self.pc {
+                halted = true;
+                break;
+            }
+            if let Err(e) = self.tick() {
+                error = Some(e);
+                break;
+            }
+            if self.is_halted() {
+                halted = true;
+                break;
+            }
+            cycles += 1;
+            if cycles % ticks_per_frame == 0 {
+                self.tick_timers();
+            }
+        }
+
+        RunSummary {
+            cycles,
+            lit_pixels: self.get_display().iter().filter(|p| **p).count(),
+            halted,
+            error,
+            state_hash: self.state_hash(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_headless_stops_on_a_spin_loop() {
+        let mut emu = Emu::new();
+        // LD F, V0; DRW V0, V0, 5; JMP 0x204
+        emu.load(&[0xF0, 0x29, 0xD0, 0x05, 0x12, 0x04]).unwrap();
+        let summary = emu.run_headless(100, &InputSchedule::new());
+        assert_eq!(summary.cycles, 2);
+        // the "0" glyph has 14 pixels set
+        assert_eq!(summary.lit_pixels, 14);
+        assert!(summary.halted);
+        assert_eq!(summary.error, None);
+        assert_eq!(summary.state_hash, emu.state_hash());
+    }
+
+    #[test]
+    fn run_headless_applies_scheduled_input() {
+        let mut emu = Emu::new();
+        // LD V1, K; JMP 0x202
+        emu.load(&[0xF1, 0x0A, 0x12, 0x02]).unwrap();
+        let mut inputs = InputSchedule::new();
+        inputs.press(10, 0x5);
+        let summary = emu.run_headless(100, &inputs);
+        // FX0A repeats until the key goes down before cycle 10
+        assert_eq!(summary.cycles, 11);
+        assert!(summary.halted);
+        assert_eq!(emu.registers()[1], 0x5);
+    }
+
+    #[test]
+    fn run_headless_stops_at_max_cycles() {
+        let mut emu = Emu::new();
+        // ADD V0, 1; JMP 0x200
+        emu.load(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        let summary = emu.run_headless(50, &InputSchedule::new());
+        assert_eq!(summary.cycles, 50);
+        assert!(!summary.halted);
+        assert_eq!(emu.registers()[0], 25);
+    }
+
+    #[test]
+    fn run_headless_reports_unknown_opcodes() {
+        let mut emu = Emu::new();
+        // LD V0, 1; SYS 0x123
+        emu.load(&[0x60, 0x01, 0x01, 0x23]).unwrap();
+        let summary = emu.run_headless(100, &InputSchedule::new());
+        assert_eq!(summary.cycles, 1);
+        assert_eq!(summary.error, Some(Chip8Error::UnknownOpcode(0x0123)));
+    }
+
+    #[test]
+    fn run_frame_reports_display_changes() {
+        let mut emu = Emu::new();
+        emu.load(&[
+            0x60, 0x01, // 0x200 LD V0, 1
+            0x61, 0x02, // 0x202 LD V1, 2
+            0xD0, 0x15, // 0x204 DRW V0, V1, 5
+            0x12, 0x06, // 0x206 JMP 0x206
+        ])
+        .unwrap();
+        assert_eq!(emu.run_frame(2), Ok(false));
+        assert_eq!(emu.run_frame(2), Ok(true));
+        assert_eq!(emu.run_frame(2), Ok(false));
+    }
+
+    #[test]
+    fn run_frame_reports_a_one_frame_beep() {
+        let mut emu = Emu::new();
+        // LD V0, 1; LD ST, V0; JMP 0x204
+        emu.load(&[0x60, 0x01, 0xF0, 0x18, 0x12, 0x04]).unwrap();
+        emu.run_frame(2).unwrap();
+        assert!(emu.frame_beeping());
+        // the timer tick already ran ST back down to 0
+        assert!(!emu.is_beeping());
+        emu.run_frame(2).unwrap();
+        assert!(!emu.frame_beeping());
+    }
+
+    // jumps forward by exactly the requested time (times `lag`, to play a slow host)
+    struct FakeClock {
+        now: Duration,
+        lag: u32,
+        sleeps: usize,
+    }
+
+    impl FakeClock {
+        fn new(lag: u32) -> Self {
+            Self { now: Duration::ZERO, lag, sleeps: 0 }
+        }
+    }
+
+    impl Clock for FakeClock {
+        fn now(&self) -> Duration {
+            self.now
+        }
+
+        fn sleep(&mut self, duration: Duration) {
+            assert!(!duration.is_zero(), "run_for asked for a zero length sleep");
+            self.now += duration * self.lag;
+            self.sleeps += 1;
+        }
+    }
+
+    // RND V1, 0xFF; ADD V0, 1; LD F, V0; DRW V1, V1, 5; JMP 0x200
+    const BUSY: [u8; 10] = [0xC1, 0xFF, 0x70, 0x01, 0xF0, 0x29, 0xD1, 0x15, 0x12, 0x00];
+
+    fn busy_emu() -> Emu {
+        let mut emu = Emu::new();
+        emu.set_rng_seed(1);
+        emu.load(&BUSY).unwrap();
+        emu
+    }
+
+    #[test]
+    fn run_for_runs_one_frame_per_timer_tick() {
+        let mut emu = busy_emu();
+        let mut clock = FakeClock::new(1);
+        let summary = emu.run_for(Duration::from_secs(1), &mut clock);
+        assert_eq!(summary.cycles, TIMER_HZ as usize * emu.ticks_per_frame());
+        assert_eq!(summary.error, None);
+        assert_eq!(clock.now(), Duration::from_secs(1));
+        assert_eq!(clock.sleeps, TIMER_HZ as usize);
+    }
+
+    #[test]
+    fn run_for_result_does_not_depend_on_the_host_speed() {
+        let mut on_time = busy_emu();
+        let expected = on_time.run_for(Duration::from_millis(500), &mut FakeClock::new(1));
+
+        let mut late = busy_emu();
+        let mut clock = FakeClock::new(3);
+        let summary = late.run_for(Duration::from_millis(500), &mut clock);
+        assert_eq!(summary, expected);
+        // late frames are caught up without sleeping in between
+        assert!(clock.sleeps < 30);
+
+        // and a plain repeat with the same seed matches too
+        let mut again = busy_emu();
+        assert_eq!(again.run_for(Duration::from_millis(500), &mut FakeClock::new(1)), expected);
+    }
+
+    #[test]
+    fn run_for_stops_on_errors() {
+        let mut emu = Emu::new();
+        // RET
+        emu.load(&[0x00, 0xEE]).unwrap();
+        let summary = emu.run_for(Duration::from_secs(1), &mut FakeClock::new(1));
+        assert_eq!(summary.error, Some(Chip8Error::StackUnderflow));
+        assert_eq!(summary.cycles, 0);
+    }
+
+    #[test]
+    fn run_for_handles_the_longest_duration() {
+        let mut emu = Emu::new();
+        emu.load(&[0x00, 0xEE]).unwrap();
+        let summary = emu.run_for(Duration::MAX, &mut FakeClock::new(1));
+        assert_eq!(summary.error, Some(Chip8Error::StackUnderflow));
+        // the last frame still ends at a real time instead of wrapping
+        assert!(frame_end(u64::MAX - 1) > frame_end(u64::MAX / 2));
+    }
+}
@@ -0,0 +1,173 @@
+use crate::{Emu, NUM_KEYS};
+
+// state of the whole 16 key keypad, indexed by CHIP-8 key (0x0 - 0xF)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct InputState {
+    pub keys: [bool; NUM_KEYS],
+}
+
+impl InputState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // key is the CHIP-8 key, 0x0 - 0xF. anything else is ignored, same as Emu::keypress
+    pub fn set(&mut self, key: usize, pressed: bool) {
+        if key < NUM_KEYS {
+            self.keys[key] = pressed;
+        }
+    }
+
+    // keys outside 0x0 - 0xF are never pressed
+    pub fn is_pressed(&self, key: usize) -> bool {
+        key < NUM_KEYS && self.keys[key]
+    }
+}
+
+// called before every frame with the current machine, returns the keys to hold for that frame
+// Send so an emulator with a hook can still be moved to a worker thread
+pub type InputHook = Box<dyn FnMut(&Emu) -> InputState + Send>;
+
+impl Emu {
+    pub fn set_keys(&mut self, input: InputState) {
+        // keys that go down together count as pressed in index order
+        for key in 0..NUM_KEYS {
+            if input.keys[key] && !self.keys[key] {
+                self.key_presses += 1;
+                self.key_press_order[key] = self.key_presses;
+            }
+        }
+        self.keys = input.keys;
+        self.key_held_frames = [0; NUM_KEYS];
+    }
+
+    pub fn input_state(&self) -> InputState {
+        InputState { keys: self.keys }
+    }
+
+    // plug in a scripted or AI controller, it replaces the whole keypad every frame
+    pub fn set_input_hook<F>(&mut self, hook: F)
+    where
+        F: FnMut(&Emu) -> InputState + Send + 'static,
+    {
+        self.input_hook = Some(Box::new(hook));
+    }
+
+    pub fn clear_input_hook(&mut self) {
+        self.input_hook = None;
+    }
+
+    // release any key held for more than `frames` frames without being pressed again,
+    // so a missed key up event (e.g. the window losing focus) can't leave a key stuck forever
+    pub fn set_key_timeout(&mut self, frames: Option<u32>) {
+        self.key_timeout = frames;
+    }
+
+    // runs once per frame from tick_timers
+    pub(crate) fn tick_key_watchdog(&mut self) {
+        let Some(timeout) = self.key_timeout else {
+            return;
+        };
+        for key in 0..NUM_KEYS {
+            if !self.keys[key] {
+                continue;
+            }
+            self.key_held_frames[key] += 1;
+            if self.key_held_frames[key] > timeout {
+                self.keys[key] = false;
+                self.key_held_frames[key] = 0;
+            }
+        }
+    }
+
+    // frontends call this once at the start of every frame, before running the frame's instructions
+    pub fn begin_frame(&mut self) {
+        // the hook needs to borrow the emulator, so take it out while it runs
+        if let Some(mut hook) = self.input_hook.take() {
+            let input = hook(self);
+            self.set_keys(input);
+            self.input_hook = Some(hook);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::InputSchedule;
+
+    #[test]
+    fn bot_presses_a_key_when_a_register_hits_a_value() {
+        let mut emu = Emu::new();
+        emu.load(&[
+            0x70, 0x01, // 0x200 ADD V0, 1
+            0xE5, 0x9E, // 0x202 SKP V5
+            0x12, 0x00, // 0x204 JMP 0x200
+            0x12, 0x06, // 0x206 JMP 0x206
+        ])
+        .unwrap();
+        emu.set_input_hook(|emu| {
+            let mut input = InputState::new();
+            input.set(0x0, emu.registers()[0] >= 3);
+            input
+        });
+        let summary = emu.run_headless(100, &InputSchedule::new());
+        // the hook runs at the start of each 10 instruction frame, V0 is 4 when the second one starts
+        assert!(summary.halted);
+        assert_eq!(summary.cycles, 11);
+        assert_eq!(emu.registers()[0], 4);
+        assert!(emu.input_state().is_pressed(0x0));
+    }
+
+    #[test]
+    fn out_of_range_keys_are_ignored() {
+        let mut input = InputState::new();
+        input.set(16, true);
+        input.set(0xF, true);
+        assert!(!input.is_pressed(16));
+        assert!(input.is_pressed(0xF));
+        assert_eq!(input.keys.iter().filter(|k| **k).count(), 1);
+    }
+
+    #[test]
+    fn emu_is_send() {
+        fn assert_send<T: Send>() {}
+        assert_send::<Emu>();
+    }
+
+    #[test]
+    fn key_held_past_the_timeout_is_released() {
+        let mut emu = Emu::new();
+        emu.set_key_timeout(Some(3));
+        emu.keypress(0x4, true);
+        for _ in 0..3 {
+            emu.tick_timers();
+        }
+        assert!(emu.input_state().is_pressed(0x4));
+        emu.tick_timers();
+        assert!(!emu.input_state().is_pressed(0x4));
+    }
+
+    #[test]
+    fn refreshed_key_stays_down() {
+        let mut emu = Emu::new();
+        emu.set_key_timeout(Some(3));
+        emu.keypress(0x4, true);
+        for _ in 0..10 {
+            emu.tick_timers();
+            // key repeat
+            emu.keypress(0x4, true);
+        }
+        assert!(emu.input_state().is_pressed(0x4));
+    }
+
+    #[test]
+    fn keys_never_time_out_by_default() {
+        let mut emu = Emu::new();
+        emu.keypress(0x4, true);
+        for _ in 0..1000 {
+            emu.tick_timers();
+        }
+        assert!(emu.input_state().is_pressed(0x4));
+    }
+}
@@ -1,15 +1,49 @@
-use std::usize;
-use rand::{random, seq::index};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+mod debug;
+mod disasm;
+mod display;
+mod error;
+mod events;
+mod headless;
+mod input;
+mod op;
+mod quirks;
+mod state;
+mod trace;
+
+pub use debug::{StepResult, StopReason};
+pub use disasm::disassemble;
+pub use display::unpack_display;
+pub use error::Chip8Error;
+pub use events::{EmuEvent, EventCallback};
+pub use headless::{Clock, InputEvent, InputSchedule, RunSummary, SystemClock};
+pub use input::{InputHook, InputState};
+pub use op::Op;
+pub use quirks::{KeyWaitPolicy, Quirks};
+pub use state::StateDiff;
+pub use trace::{TraceEntry, TraceMismatch};
 
 pub const SCREEN_WIDTH: usize = 64;
 pub const SCREEN_HEIGHT: usize = 32;
+// SUPER-CHIP high resolution mode, switched on with 00FF and off with 00FE
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
 
 const RAM_SIZE: usize = 4096;
 const NUM_REGS: usize = 16; // array sizes have to be of size usize
 const STACK_SIZE: usize = 16;
 const START_ADDR: u16 = 0x200; // 512 in decimal, which is the standard starting address for executables in chip8
 const FONTSET_SIZE: usize = 80;
-const NUM_KEYS: usize = 16;
+pub const NUM_KEYS: usize = 16;
+// number of cycles a ROM can run without clearing or drawing before we consider the screen suspicious
+// (around 5 seconds at the default speed of 10 instructions per frame)
+pub const DISPLAY_INIT_WARN_CYCLES: usize = 3000;
+// the delay and sound timers count down at 60Hz, frontends run one frame per timer tick
+pub const TIMER_HZ: u32 = 60;
+// instructions per second when nothing else is configured, 10 per frame
+pub const DEFAULT_CPU_HZ: u32 = 600;
 
 const FONTSET: [u8; FONTSET_SIZE] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
@@ -30,10 +64,24 @@ const FONTSET: [u8; FONTSET_SIZE] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
 
+// what to do when PC runs off the end of RAM while executing sequentially
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PcOverflow {
+    // start over from address 0
+    Wrap,
+    // stop executing, tick() becomes a no-op until reset
+    Halt,
+    // report Chip8Error::PcOutOfBounds from tick()
+    #[default]
+    Error,
+}
+
 pub struct Emu {
     pc: u16,
     ram: [u8; RAM_SIZE],
-    screen: [bool; SCREEN_WIDTH * SCREEN_HEIGHT],
+    // sized for hires, in lores only the first SCREEN_WIDTH * SCREEN_HEIGHT pixels are used
+    screen: [bool; HIRES_WIDTH * HIRES_HEIGHT],
+    hires: bool,
     v_reg: [u8; NUM_REGS],
     i_reg: u16,
     sp: u16,
@@ -41,6 +89,43 @@ pub struct Emu {
     keys: [bool; NUM_KEYS],
     dt: u8,
     st: u8,
+    // number of executed instructions since the last reset
+    cycles: usize,
+    // set once the ROM issues a CLS or DXYN
+    display_used: bool,
+    // set by CLS and DXYN, cleared at the start of every run_frame
+    display_dirty: bool,
+    // set by run_frame when the sound timer was running before that frame's timer tick
+    frame_beeping: bool,
+    pc_overflow: PcOverflow,
+    halted: bool,
+    subscribers: Vec<EventCallback>,
+    input_hook: Option<InputHook>,
+    quirks: Quirks,
+    // frames each key has been held since it was last pressed or refreshed
+    key_held_frames: [u32; NUM_KEYS],
+    // release keys held longer than this many frames without a refresh, None to never release
+    key_timeout: Option<u32>,
+    // when each key last went down (bigger is more recent), counted by key_presses
+    key_press_order: [u64; NUM_KEYS],
+    key_presses: u64,
+    // debugger state for step_n
+    breakpoints: Vec<u16>,
+    watches: Vec<u16>,
+    // reject ROM writes into the fontset so FX29 glyphs can't get clobbered
+    font_protection: bool,
+    last_instruction: Option<TraceEntry>,
+    // instructions per second, frontends and the headless runners spread this over TIMER_HZ frames
+    cpu_hz: u32,
+    // source for CXNN, seeded from the OS unless set_rng_seed picked a fixed seed
+    rng: StdRng,
+    rng_seed: Option<u64>,
+}
+
+impl Default for Emu {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Emu {
@@ -48,7 +133,8 @@ impl Emu {
         let mut new_emu = Self {
             pc: START_ADDR,
             ram: [0; RAM_SIZE],
-            screen: [false; SCREEN_HEIGHT * SCREEN_WIDTH],
+            screen: [false; HIRES_WIDTH * HIRES_HEIGHT],
+            hires: false,
             v_reg: [0; NUM_REGS],
             i_reg: 0,
             sp: 0,
@@ -56,302 +142,503 @@ impl Emu {
             keys: [false; NUM_KEYS],
             dt: 0,
             st: 0,
+            cycles: 0,
+            display_used: false,
+            display_dirty: false,
+            frame_beeping: false,
+            pc_overflow: PcOverflow::default(),
+            halted: false,
+            subscribers: Vec::new(),
+            input_hook: None,
+            quirks: Quirks::default(),
+            key_held_frames: [0; NUM_KEYS],
+            key_timeout: None,
+            key_press_order: [0; NUM_KEYS],
+            key_presses: 0,
+            breakpoints: Vec::new(),
+            watches: Vec::new(),
+            font_protection: false,
+            last_instruction: None,
+            cpu_hz: DEFAULT_CPU_HZ,
+            rng: StdRng::from_entropy(),
+            rng_seed: None,
         };
         // load fonts into the first FONTSET_SIZE elements in ram
         // copy_from_slice ensures that both sides have the same size, otherwise it panics
         new_emu.ram[..FONTSET_SIZE].copy_from_slice(&FONTSET);
+        debug_assert!(new_emu.verify_font());
 
         new_emu
     }
 
-    fn push(&mut self, val: u16) {
+    // true if the first FONTSET_SIZE bytes of RAM still hold the built-in font that FX29 points into
+    pub fn verify_font(&self) -> bool {
+        self.ram[..FONTSET_SIZE] == FONTSET
+    }
+
+    fn push(&mut self, val: u16) -> Result<(), Chip8Error> {
+        // a ROM nesting more than STACK_SIZE calls would write past the end of the stack
+        if self.sp as usize >= STACK_SIZE {
+            return Err(Chip8Error::StackOverflow);
+        }
         // indexing in rust requires usize type
         self.stack[self.sp as usize] = val;
         self.sp += 1;
+        self.emit(EmuEvent::StackPushed(val));
+        Ok(())
     }
-    fn pop(&mut self) -> u16 {
+    fn pop(&mut self) -> Result<u16, Chip8Error> {
+        // RET without a matching CALL
+        if self.sp == 0 {
+            return Err(Chip8Error::StackUnderflow);
+        }
         self.sp -= 1;
-        self.stack[self.sp as usize]
+        Ok(self.stack[self.sp as usize])
     }
 
     pub fn reset(&mut self) {
         self.pc = START_ADDR;
         self.ram = [0; RAM_SIZE];
-        self.screen = [false; SCREEN_HEIGHT * SCREEN_WIDTH];
+        self.screen = [false; HIRES_WIDTH * HIRES_HEIGHT];
+        self.hires = false;
         self.v_reg = [0; NUM_REGS];
         self.i_reg = 0;
         self.sp = 0;
         self.stack = [0; STACK_SIZE];
         self.dt = 0;
         self.st = 0;
+        self.cycles = 0;
+        self.display_used = false;
+        self.display_dirty = false;
+        self.frame_beeping = false;
+        self.halted = false;
+        self.last_instruction = None;
+        // start the random sequence over too, so a seeded run replays the same way after a reset
+        if let Some(seed) = self.rng_seed {
+            self.rng = StdRng::seed_from_u64(seed);
+        }
         self.ram[..FONTSET_SIZE].copy_from_slice(&FONTSET);
+        debug_assert!(self.verify_font());
     }
 
-    pub fn tick(&mut self) {
+    pub fn tick(&mut self) -> Result<(), Chip8Error> {
+        self.step().map(|_| ())
+    }
+
+    // same as tick, but hands back the decoded instruction so debuggers can show what just ran
+    // (a halted emulator doesn't execute anything and reports a NOP)
+    pub fn step(&mut self) -> Result<Op, Chip8Error> {
+        let result = self.run_instruction();
+        if let Err(e) = result {
+            self.emit(EmuEvent::Error(e));
+        }
+        result
+    }
+
+    fn run_instruction(&mut self) -> Result<Op, Chip8Error> {
+        if self.halted {
+            return Ok(Op::Nop);
+        }
+        // instructions are two bytes, so PC needs room for the second byte as well
+        if self.pc as usize + 1 >= RAM_SIZE {
+            match self.pc_overflow {
+                PcOverflow::Wrap => self.pc = 0,
+                PcOverflow::Halt => {
+                    self.halted = true;
+                    return Ok(Op::Nop);
+                },
+                PcOverflow::Error => return Err(Chip8Error::PcOutOfBounds(self.pc)),
+            }
+        }
+        let pc = self.pc;
         // fetch
         let op = self.fetch();
         // decode and execute
-        self.execute(op);
+        let decoded = Op::decode(op);
+        self.execute(decoded)?;
+        self.cycles += 1;
+        self.last_instruction = Some(TraceEntry { pc, op });
+        self.emit(EmuEvent::OpcodeExecuted { pc, op });
+        Ok(decoded)
+    }
+
+    // make CXNN deterministic, two machines with the same seed and input produce the same numbers
+    pub fn set_rng_seed(&mut self, seed: u64) {
+        self.rng_seed = Some(seed);
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    pub fn set_cpu_hz(&mut self, hz: u32) {
+        self.cpu_hz = hz;
+    }
+
+    pub fn cpu_hz(&self) -> u32 {
+        self.cpu_hz
+    }
+
+    // instructions to run per timer tick at the configured CPU rate, rounded to the nearest whole
+    // instruction and at least one
+    pub fn ticks_per_frame(&self) -> usize {
+        // widened so the rounding can't overflow near u32::MAX
+        ((self.cpu_hz as u64 + TIMER_HZ as u64 / 2) / TIMER_HZ as u64).max(1) as usize
+    }
+
+    pub fn set_pc_overflow(&mut self, policy: PcOverflow) {
+        self.pc_overflow = policy;
+    }
+
+    // true once PC ran off the end of RAM with the Halt policy
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    pub fn cycles(&self) -> usize {
+        self.cycles
+    }
+
+    // true if the ROM has been running for a while without ever clearing or drawing to the screen,
+    // which usually means it is stuck or expects something the interpreter doesn't provide
+    pub fn display_never_initialized(&self) -> bool {
+        !self.display_used && self.cycles >= DISPLAY_INIT_WARN_CYCLES
     }
 
     fn fetch(&mut self) -> u16 {
+        let op = self.peek_op();
+        self.pc += 2;
+        op
+    }
+
+    // read the instruction at PC without advancing
+    fn peek_op(&self) -> u16 {
         // since the ram is using u8 (byte) values, and the instruction is u16 (2 bytes)
         // we have to fetch two bytes at a time
         let higher_byte = self.ram[self.pc as usize] as u16; // ex: 0x12 --> 0x0012
         let lower_byte = self.ram[(self.pc + 1) as usize] as u16; // ex : 0x34 --> 0x0034
-        let op = (higher_byte << 8) | lower_byte; // (0x0012 << 8) --> 0x1200 | 0x0034 --> 0x1234
-        self.pc += 2;
-        op
+        (higher_byte << 8) | lower_byte // (0x0012 << 8) --> 0x1200 | 0x0034 --> 0x1234
+    }
+
+    // off by default, real hardware lets ROMs write anywhere
+    pub fn set_font_protection(&mut self, enabled: bool) {
+        self.font_protection = enabled;
+    }
+
+    // make sure len bytes starting at addr are all inside RAM, I can point anywhere up to 0xFFFF
+    fn check_in_ram(&self, addr: usize, len: usize) -> Result<(), Chip8Error> {
+        if addr + len > RAM_SIZE {
+            return Err(Chip8Error::AddressOutOfBounds(addr as u16));
+        }
+        Ok(())
+    }
+
+    // make sure a ROM store of len bytes starting at addr is allowed, before anything is written
+    fn check_writable(&self, addr: usize, len: usize) -> Result<(), Chip8Error> {
+        if self.font_protection && addr < FONTSET_SIZE && len > 0 {
+            return Err(Chip8Error::FontWrite(addr as u16));
+        }
+        Ok(())
     }
 
-    // decode and execute
-    fn execute(&mut self, op: u16) {
-        // opcodes have 4 hex digits, to decode the opcode, we need to separate each digit
-        let digit1 = (op & 0xF000) >> 12; // shift by 3 hex digits or 12 bits
-        let digit2 = (op & 0x0F00) >> 8;  // shift by 2 hex digits or 8 bits
-        let digit3 = (op & 0x00F0) >> 4;  // shift by 1 hex digits or 4 bits
-        let digit4 = op & 0x000F;
-        // match statement to sepcify match pattern for our opcode
-        match (digit1, digit2, digit3, digit4) {
+    // carry/borrow/collision flag writes go through here so VF changes can be traced back to their cause
+    fn set_vf(&mut self, cause: Op, value: u8) {
+        self.v_reg[0xF] = value;
+        self.emit(EmuEvent::VfWritten { cause, value });
+    }
+
+    // plain VX writes go through here, so an instruction that targets VF is traced like a flag write
+    fn set_reg(&mut self, cause: Op, x: usize, value: u8) {
+        if x == 0xF {
+            self.set_vf(cause, value);
+        } else {
+            self.v_reg[x] = value;
+        }
+    }
+
+    // shift the active screen by (dx, dy) pixels, whatever scrolls off is lost and the exposed area is blank
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        if !self.hires && !self.quirks.lores_scroll_enabled {
+            return;
+        }
+        let width = self.screen_width() as isize;
+        let height = self.screen_height() as isize;
+        let old = self.screen;
+        for y in 0..height {
+            for x in 0..width {
+                let (src_x, src_y) = (x - dx, y - dy);
+                let inside = (0..width).contains(&src_x) && (0..height).contains(&src_y);
+                self.screen[(x + width * y) as usize] = inside && old[(src_x + width * src_y) as usize];
+            }
+        }
+        self.display_dirty = true;
+    }
+
+    // execute a decoded instruction
+    fn execute(&mut self, op: Op) -> Result<(), Chip8Error> {
+        match op {
             // 0x0000 NOP
-            (0, 0, 0, 0) => return,
+            Op::Nop => (),
             // 0x00E0 CLS
-            (0, 0, 0xE, 0) => {
-                self.screen = [false; SCREEN_WIDTH * SCREEN_HEIGHT];
+            Op::ClearScreen => {
+                self.screen = [false; HIRES_WIDTH * HIRES_HEIGHT];
+                self.display_used = true;
+                self.display_dirty = true;
+                self.emit(EmuEvent::ScreenCleared);
+            },
+            // 0x00CN SCD, 0x00FB SCR, 0x00FC SCL
+            Op::ScrollDown(n) => {
+                self.scroll(0, n as isize);
+            },
+            Op::ScrollRight => {
+                self.scroll(4, 0);
+            },
+            Op::ScrollLeft => {
+                self.scroll(-4, 0);
+            },
+            // 0x00FE LOW, 0x00FF HIGH
+            Op::LowRes | Op::HighRes => {
+                self.hires = op == Op::HighRes;
+                // the row stride changes with the mode so whatever was drawn before is garbage now
+                self.screen = [false; HIRES_WIDTH * HIRES_HEIGHT];
+                self.display_dirty = true;
             },
             // 0x00EE RET
-            (0, 0, 0xE, 0xE) => {
+            Op::Return => {
                 // pop the return address from the stack
-                let ret_addr = self.pop();
+                let ret_addr = self.pop()?;
                 // put the the return address into PC
                 self.pc = ret_addr;
             },
             // 0x1NNN JMP
-            (1, _, _, _) => {
+            Op::Jump(addr) => {
                 // take the address NNN and put it in the PC
-                let addr = op & 0xFFF;
                 self.pc = addr;
             },
             // 0x2NNN CALL
-            (2, _, _, _) => {
-                // Take the address NNN from the opcode
-                let addr = op & 0xFFF;
+            Op::Call(addr) => {
                 // push the address into the stack
-                self.push(self.pc);
+                self.push(self.pc)?;
                 // put the address in PC
                 self.pc = addr;
             },
             // 0x3XNN SKIP VX == NN
-            (3, _, _, _) => {
-                let x = digit2 as usize;
-                let nn = (op & 0xFF) as u8;
+            Op::SkipEqImm { x, nn } => {
+                let x = x as usize;
                 if self.v_reg[x] == nn {
                     self.pc += 2;
                 }
             },
             // 0x4XNN SKIP VX != NN
-            (4, _, _, _) => {
-                let x = digit2 as usize;
-                let nn = (op & 0xFF) as u8;
+            Op::SkipNeImm { x, nn } => {
+                let x = x as usize;
                 if self.v_reg[x] != nn {
                     self.pc += 2;
                 }
             },
             // 0x5XY0 SKIP VX == VY
-            (5, _, _, 0) => {
-                let x = digit2 as usize;
-                let y = digit3 as usize;
+            Op::SkipEqReg { x, y } => {
+                let x = x as usize;
+                let y = y as usize;
                 if self.v_reg[x] == self.v_reg[y] {
                     self.pc += 2;
                 }
             },
             // 0x6XNN VX = NN (similar to MOV)
-            (6, _, _, _) => {
-                let x = digit2 as usize;
-                let nn = (op & 0xFF) as u8;
-                self.v_reg[x] = nn;
+            Op::LoadImm { x, nn } => {
+                let x = x as usize;
+                self.set_reg(op, x, nn);
             },
             // 0x7XNN VX += NN
-            (7, _, _, _) => {
-                let x = digit2 as usize;
-                let nn = (op & 0xFF) as u8;
+            Op::AddImm { x, nn } => {
+                let x = x as usize;
                 // using wrapping_add instead of += in case of integer overflow that might panic
-                self.v_reg[x] = self.v_reg[x].wrapping_add(nn);
+                self.set_reg(op, x, self.v_reg[x].wrapping_add(nn));
             },
             // 0x8XY0 VX = VY
-            (8, _, _, 0) => {
-                let x = digit2 as usize;
-                let y = digit3 as usize;
-                self.v_reg[x] = self.v_reg[y];
+            Op::Move { x, y } => {
+                let x = x as usize;
+                let y = y as usize;
+                self.set_reg(op, x, self.v_reg[y]);
             },
             // 0x8XY1 VX |= VY
-            (8, _, _, 1) => {
-                let x = digit2 as usize;
-                let y = digit3 as usize;
-                self.v_reg[x] |= self.v_reg[y];
+            Op::Or { x, y } => {
+                let x = x as usize;
+                let y = y as usize;
+                self.set_reg(op, x, self.v_reg[x] | self.v_reg[y]);
             },
             // 0x8XY2 VX &= VY
-            (8, _, _, 2) => {
-                let x = digit2 as usize;
-                let y = digit3 as usize;
-                self.v_reg[x] &= self.v_reg[y];
+            Op::And { x, y } => {
+                let x = x as usize;
+                let y = y as usize;
+                self.set_reg(op, x, self.v_reg[x] & self.v_reg[y]);
             },
             // 0x8XY3 VX ^= VY
-            (8, _, _, 3) => {
-                let x = digit2 as usize;
-                let y = digit3 as usize;
-                self.v_reg[x] ^= self.v_reg[y];
+            Op::Xor { x, y } => {
+                let x = x as usize;
+                let y = y as usize;
+                self.set_reg(op, x, self.v_reg[x] ^ self.v_reg[y]);
             },
             // 0x8XY4 VX += VY
-            (8, _, _, 4) => {
-                let x = digit2 as usize;
-                let y = digit3 as usize;
+            Op::AddReg { x, y } => {
+                let x = x as usize;
+                let y = y as usize;
 
                 // check for carry, if there is carry set the carry flag in register VF
                 let (new_vx, carry) = self.v_reg[x].overflowing_add(self.v_reg[y]);
                 let new_vf = if carry {1} else {0};
 
                 self.v_reg[x] = new_vx;
-                self.v_reg[0xF] = new_vf;
+                self.set_vf(op, new_vf);
             },
             // 0x8XY5 VX -= VY
-            (8, _, _, 5) => {
-                let x = digit2 as usize;
-                let y = digit3 as usize;
+            Op::SubReg { x, y } => {
+                let x = x as usize;
+                let y = y as usize;
 
                 let (new_vx, borrow) = self.v_reg[x].overflowing_sub(self.v_reg[y]);
                 let new_vf = if borrow {0} else {1};
 
                 self.v_reg[x] = new_vx;
-                self.v_reg[0xF] = new_vf;
+                self.set_vf(op, new_vf);
 
             },
             // 0x8XY6 VX >>= 1
-            (8, _, _, 6) => {
-                let x = digit2 as usize;
-                let lsb = self.v_reg[x] & 1;
-                self.v_reg[x] >>= 1;
-                self.v_reg[0xF] = lsb;
+            Op::ShiftRight { x, y } => {
+                let x = x as usize;
+                let y = y as usize;
+                // some interpreters shift VY into VX, others shift VX in place
+                let src = if self.quirks.shift_uses_vy { self.v_reg[y] } else { self.v_reg[x] };
+                let lsb = src & 1;
+                self.v_reg[x] = src >> 1;
+                self.set_vf(op, lsb);
             },
             // 0x8XY7 VX = VY - VX
-            (8, _, _, 7) => {
-                let x = digit2 as usize;
-                let y = digit3 as usize;
+            Op::SubNeg { x, y } => {
+                let x = x as usize;
+                let y = y as usize;
 
                 let (new_vx, borrow) = self.v_reg[y].overflowing_sub(self.v_reg[x]);
                 let new_vf = if borrow {0} else {1};
 
                 self.v_reg[x] = new_vx;
-                self.v_reg[0xF] = new_vf;
+                self.set_vf(op, new_vf);
             },
             // 0x8XYE VX <<= 1
-            (8, _, _, 0xE) => {
-                let x = digit2 as usize;
-                let msb = (self.v_reg[x] >> 7) & 1;
-                self.v_reg[x] <<= 1;
-                self.v_reg[0xF] = msb;
+            Op::ShiftLeft { x, y } => {
+                let x = x as usize;
+                let y = y as usize;
+                let src = if self.quirks.shift_uses_vy { self.v_reg[y] } else { self.v_reg[x] };
+                let msb = (src >> 7) & 1;
+                self.v_reg[x] = src << 1;
+                self.set_vf(op, msb);
             },
             // 0x9XY0 SKIP VX != VY
-            (9, _, _, 0) => {
-                let x = digit2 as usize;
-                let y = digit3 as usize;
+            Op::SkipNeReg { x, y } => {
+                let x = x as usize;
+                let y = y as usize;
 
                 if self.v_reg[x] != self.v_reg[y] {
                     self.pc += 2;
                 }
             },
             // ANNN I = NNN
-            (0xA, _, _, _) => {
-                let nnn = op & 0xFFF;
+            Op::LoadI(nnn) => {
                 self.i_reg = nnn;
             },
             // BNNN JMP to V0 + NNN
-            (0xB, _, _, _) => {
-                let nnn = op & 0xFFF;
-                self.pc = (self.v_reg[0] as u16) + nnn;
+            Op::JumpV0(nnn) => {
+                // mask to 12 bits like the other jumps, V0 + NNN can go past 0xFFF
+                self.pc = ((self.v_reg[0] as u16) + nnn) & 0xFFF;
             },
             // CXNN  VX = rand() & NN
-            (0xC, _, _, _) => {
-                let x = digit2 as usize;
-                let nn = (op & 0xFF) as u8;
-                // have to specify u8 for random() to know which type is gonna be generated
-                let rng: u8 = random();
-                self.v_reg[x] = rng & nn;
+            Op::Random { x, nn } => {
+                let x = x as usize;
+                // have to specify u8 for gen() to know which type is gonna be generated
+                let rng: u8 = self.rng.gen();
+                self.set_reg(op, x, rng & nn);
             },
             // DXYN Draw Sprite
-            (0xD, _, _, _) => {
+            Op::DrawSprite { x, y, n } => {
                 // get the X and Y coordinates
-                let x_coord = self.v_reg[digit2 as usize] as u16;
-                let y_coord = self.v_reg[digit3 as usize] as u16;
-                // The last digit (N) determines how many rows higher is the sprite
-                let num_rows = digit4;
+                let x_coord = self.v_reg[x as usize] as u16;
+                let y_coord = self.v_reg[y as usize] as u16;
+                // The last digit (N) determines how many rows higher is the sprite,
+                // in hires N = 0 draws a SUPER-CHIP 16x16 sprite stored as two bytes per row
+                let (num_rows, row_bytes) = if n == 0 && self.hires { (16, 2) } else { (n as u16, 1) };
+                let sprite_width = row_bytes * 8;
+                let width = self.screen_width();
+                let height = self.screen_height();
+                self.check_in_ram(self.i_reg as usize, (num_rows * row_bytes) as usize)?;
                 // flipped pixel tracking
                 let mut flipped = false;
                 // iterate over each row of the sprite
                 for y_line in 0..num_rows {
                     // memory address of the sprite row data
-                    let addr = self.i_reg + y_line as u16;
-                    let pixels = self.ram[addr as usize];
-                    // iterate over each column in the row, every row is 8 pixels wide
-                    for x_line in 0..8 {
+                    let addr = (self.i_reg + y_line * row_bytes) as usize;
+                    let pixels = self.ram[addr..addr + row_bytes as usize]
+                        .iter()
+                        .fold(0u16, |row, byte| (row << 8) | *byte as u16);
+                    // iterate over each column in the row, the leftmost pixel is the most significant bit
+                    for x_line in 0..sprite_width {
                         // fetch pixels using a mask
-                        if (pixels & (0b1000_0000 >> x_line)) != 0 {
+                        if (pixels & (1 << (sprite_width - 1 - x_line))) != 0 {
                             // wrap around screen using modulo
-                            let x = (x_coord + x_line) as usize % SCREEN_WIDTH;
-                            let y = (y_coord + y_line) as usize % SCREEN_HEIGHT;
+                            let x = (x_coord + x_line) as usize % width;
+                            let y = (y_coord + y_line) as usize % height;
 
                             // get pixel index for the 1D screen array
-                            let index = x + SCREEN_WIDTH * y;
+                            let index = x + width * y;
                             // check flipping
                             flipped |= self.screen[index];
                             self.screen[index] ^= true;
                         }
                     }
                 }
+                self.display_used = true;
+                self.display_dirty = true;
                 // if the pixel flipped set VF regsiter
                 if flipped {
-                    self.v_reg[0xF] = 1;
+                    self.set_vf(op, 1);
                 } else {
-                    self.v_reg[0xF] = 0;
+                    self.set_vf(op, 0);
                 }
+                self.emit(EmuEvent::SpriteDrawn { collided: flipped });
             },
             // EX9E Skip if key pressed
-            (0xE, _, 9, 0xE) => {
-                let x = digit2 as usize;
+            Op::SkipKeyPressed { x } => {
+                let x = x as usize;
                 let vx = self.v_reg[x];
-                let key = self.keys[vx as usize];
+                // VX past 0xF names a key that doesn't exist, so it is never pressed
+                let key = self.keys.get(vx as usize).copied().unwrap_or(false);
                 // if key pressed skip instruction
                 if key {
                     self.pc += 2;
                 }
             },
             // EXA1 Skip if key not pressed
-            (0xE, _, 0xA, 1) => {
-                let x = digit2 as usize;
+            Op::SkipKeyNotPressed { x } => {
+                let x = x as usize;
                 let vx = self.v_reg[x];
-                let key = self.keys[vx as usize];
+                // VX past 0xF names a key that doesn't exist, so it is never pressed
+                let key = self.keys.get(vx as usize).copied().unwrap_or(false);
                 // if key pressed skip instruction
                 if !key {
                     self.pc += 2;
                 }
             }, 
             // FX07 VX = DT
-            (0xF, _, 0, 7) => {
-                let x = digit2 as usize;
-                self.v_reg[x] = self.dt;
+            Op::LoadDelay { x } => {
+                let x = x as usize;
+                self.set_reg(op, x, self.dt);
             }
             // FX0A - Wait for Key Press
-            (0xF, _, 0, 0xA) => {
-                let x = digit2 as usize;
-                let mut pressed = false;
-                for i in 0..self.keys.len() {
-                    if self.keys[i] {
-                        self.v_reg[x] = i as u8;
-                        pressed = true;
-                        break;
-                    }
-                }
-                if !pressed {
+            Op::WaitKey { x } => {
+                let x = x as usize;
+                let key = match self.quirks.key_wait {
+                    KeyWaitPolicy::LowestIndex => self.first_pressed_key(),
+                    KeyWaitPolicy::MostRecent => self.last_pressed_key(),
+                };
+                if let Some(key) = key {
+                    self.set_reg(op, x, key as u8);
+                } else {
                     // repeat instruction if no key is pressed, to be stuck in a loop untill a
                     // key is pressed
                     self.pc -= 2;
@@ -359,61 +646,76 @@ impl Emu {
 
             },
             // FX15 - DT = VX
-            (0xF, _, 1, 5) => {
-                let x = digit2 as usize;
+            Op::SetDelay { x } => {
+                let x = x as usize;
                 self.dt = self.v_reg[x];
             },
             // FX18 - ST = VX
-            (0xF, _, 1, 8) => {
-                let x = digit2 as usize;
+            Op::SetSound { x } => {
+                let x = x as usize;
+                let was_silent = self.st == 0;
                 self.st = self.v_reg[x];
+                if was_silent && self.st > 0 {
+                    self.emit(EmuEvent::BeepStarted);
+                }
             },
             // FX1E - I += VX
-            (0xF, _, 1, 0xE) => {
-                let x = digit2 as usize;
+            Op::AddI { x } => {
+                let x = x as usize;
                 let vx = self.v_reg[x] as u16;
                 self.i_reg = self.i_reg.wrapping_add(vx);
             },
             // FX29 - Set I to Font Address
-            (0xF, _, 2, 9) => {
-                let x = digit2 as usize;
+            Op::LoadFont { x } => {
+                let x = x as usize;
                 let c = self.v_reg[x] as u16;
                 // note that we stored fonts at the begginning of the RAM, and each font is 5
                 // bytes so each character is stored at its index * 5 in RAM
                 self.i_reg = c * 5;
             },
             // FX33 - I = BCD of VX
-            (0xF, _, 3, 3) => {
-                let x = digit2 as usize;
-                let vx = self.v_reg[x] as f32;
-                // fetch each decimal
-                let hundreds = (vx / 100.0).floor() as u8;
-                let tens = ((vx / 10.0) % 10.0).floor() as u8;
-                let ones = (vx % 10.0) as u8;
+            Op::Bcd { x } => {
+                let x = x as usize;
+                let vx = self.v_reg[x];
+                // fetch each decimal, integer division already drops the remainder
+                let hundreds = vx / 100;
+                let tens = (vx / 10) % 10;
+                let ones = vx % 10;
                 // store in ram
+                self.check_in_ram(self.i_reg as usize, 3)?;
+                self.check_writable(self.i_reg as usize, 3)?;
                 self.ram[self.i_reg as usize] = hundreds;
                 self.ram[(self.i_reg + 1) as usize] = tens;
                 self.ram[(self.i_reg + 2) as usize] = ones;
             },
             // FX55 Store V0 -> VX into I
-            (0xF, _, 5, 5) => {
-                let x = digit2;
+            Op::StoreRegs { x } => {
+                let x = x as usize;
                 let i = self.i_reg as usize;
+                self.check_in_ram(i, x + 1)?;
+                self.check_writable(i, x + 1)?;
                 for index in 0..=x {
-                    self.ram[i + index as usize] = self.v_reg[index as usize];
+                    self.ram[i + index] = self.v_reg[index];
+                }
+                if self.quirks.mem_increments_i {
+                    self.i_reg = self.i_reg.wrapping_add(x as u16 + 1);
                 }
             },
             // FX65 Load I into V0 -> VX
-            (0xF, _, 6, 5) => {
-                let x = digit2;
+            Op::LoadRegs { x } => {
+                let x = x as usize;
                 let i = self.i_reg as usize;
+                self.check_in_ram(i, x + 1)?;
                 for index in 0..=x {
-                    self.v_reg[index as usize] = self.ram[i + index as usize];
+                    self.set_reg(op, index, self.ram[i + index]);
+                }
+                if self.quirks.mem_increments_i {
+                    self.i_reg = self.i_reg.wrapping_add(x as u16 + 1);
                 }
             },
-            (_, _, _, _) => unimplemented!("unimplemented opcode {}", op)
-
+            Op::Unknown(op) => return Err(Chip8Error::UnknownOpcode(op)),
         }
+        Ok(())
     }
 
     pub fn tick_timers(&mut self) {
@@ -421,24 +723,756 @@ impl Emu {
             self.dt -= 1;
         }
         if self.st > 0 {
-            if self.st == 1 {
-                // TODO: BEEB
-            }
             self.st -= 1;
         }
+        self.tick_key_watchdog();
+    }
+
+    // the buzzer sounds for as long as the sound timer is nonzero
+    pub fn is_beeping(&self) -> bool {
+        self.st > 0
+    }
+
+    // whether the last run_frame should sound the buzzer. is_beeping is already false after a frame
+    // that set ST=1, since the frame's timer tick counts it straight back down
+    pub fn frame_beeping(&self) -> bool {
+        self.frame_beeping
     }
 
+    // row-major at the active resolution, screen_width() pixels per row
     pub fn get_display(&self) -> &[bool] {
-        &self.screen
+        &self.screen[..self.screen_width() * self.screen_height()]
     }
 
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    pub fn screen_width(&self) -> usize {
+        if self.hires { HIRES_WIDTH } else { SCREEN_WIDTH }
+    }
+
+    pub fn screen_height(&self) -> usize {
+        if self.hires { HIRES_HEIGHT } else { SCREEN_HEIGHT }
+    }
+
+    // read-only views of the machine for debuggers and other tooling
+    pub fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    pub fn registers(&self) -> &[u8] {
+        &self.v_reg
+    }
+
+    pub fn program_counter(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn index_register(&self) -> u16 {
+        self.i_reg
+    }
+
+    // lowest index key that is currently held down, same scan order as FX0A
+    pub fn first_pressed_key(&self) -> Option<usize> {
+        self.keys.iter().position(|pressed| *pressed)
+    }
+
+    // held key that went down most recently
+    pub fn last_pressed_key(&self) -> Option<usize> {
+        (0..NUM_KEYS)
+            .filter(|key| self.keys[*key])
+            .max_by_key(|key| self.key_press_order[*key])
+    }
+
+    // index is the CHIP-8 key, 0x0 - 0xF. anything else is ignored so a frontend bug can't crash the core
     pub fn keypress(&mut self, index: usize, pressed: bool) {
+        if index >= NUM_KEYS {
+            return;
+        }
+        // key repeat events for a key that is already down don't change the press order
+        if pressed && !self.keys[index] {
+            self.key_presses += 1;
+            self.key_press_order[index] = self.key_presses;
+        }
         self.keys[index] = pressed;
+        // every press (including key repeat events) counts as a refresh for the stuck key watchdog
+        self.key_held_frames[index] = 0;
     }
 
-    pub fn load(&mut self, data: &[u8]) {
-        let start = START_ADDR as usize;
-        let end = (START_ADDR as usize) + data.len();
+    pub fn load(&mut self, data: &[u8]) -> Result<(), Chip8Error> {
+        self.load_at(START_ADDR, data)
+    }
+
+    // copy data into RAM starting at addr, for ROMs that don't expect to start at 0x200 (ETI-660 uses 0x600)
+    // pair it with set_program_counter so execution starts where the code was put
+    pub fn load_at(&mut self, addr: u16, data: &[u8]) -> Result<(), Chip8Error> {
+        let start = addr as usize;
+        let end = start + data.len();
+        // everything from addr to the end of RAM is available to the ROM
+        if end > RAM_SIZE {
+            return Err(Chip8Error::RomTooLarge { size: data.len(), max: RAM_SIZE.saturating_sub(start) });
+        }
         self.ram[start..end].copy_from_slice(data);
+        Ok(())
+    }
+
+    pub fn set_program_counter(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn emu_with(rom: &[u8]) -> Emu {
+        let mut emu = Emu::new();
+        emu.load(rom).unwrap();
+        emu
+    }
+
+    #[test]
+    fn warns_about_rom_that_never_draws() {
+        // 0x200: JMP 0x200
+        let mut emu = emu_with(&[0x12, 0x00]);
+        for _ in 0..DISPLAY_INIT_WARN_CYCLES - 1 {
+            emu.tick().unwrap();
+        }
+        assert!(!emu.display_never_initialized());
+        emu.tick().unwrap();
+        assert!(emu.display_never_initialized());
+    }
+
+    #[test]
+    fn no_warning_once_the_screen_is_cleared() {
+        // CLS, JMP 0x202
+        let mut emu = emu_with(&[0x00, 0xE0, 0x12, 0x02]);
+        for _ in 0..DISPLAY_INIT_WARN_CYCLES {
+            emu.tick().unwrap();
+        }
+        assert!(!emu.display_never_initialized());
+    }
+
+    fn lit_pixels(emu: &Emu) -> Vec<(usize, usize)> {
+        let width = emu.screen_width();
+        emu.get_display()
+            .iter()
+            .enumerate()
+            .filter(|(_, on)| **on)
+            .map(|(i, _)| (i % width, i / width))
+            .collect()
+    }
+
+    // (x, y) of every pixel of the "0" glyph drawn with its top left corner at (x, y)
+    fn zero_glyph_at(x: usize, y: usize) -> Vec<(usize, usize)> {
+        let mut pixels = Vec::new();
+        for row in 0..5 {
+            for col in 0..4 {
+                if row == 0 || row == 4 || col == 0 || col == 3 {
+                    pixels.push(((x + col) % SCREEN_WIDTH, (y + row) % SCREEN_HEIGHT));
+                }
+            }
+        }
+        pixels.sort_by_key(|(x, y)| (*y, *x));
+        pixels
+    }
+
+    #[test]
+    fn draws_font_glyph_at_vx_vy() {
+        // LD V0, 10; LD V1, 5; LD F, V2; DRW V0, V1, 5
+        let mut emu = emu_with(&[0x60, 10, 0x61, 5, 0xF2, 0x29, 0xD0, 0x15]);
+        for _ in 0..4 {
+            emu.tick().unwrap();
+        }
+        assert_eq!(lit_pixels(&emu), zero_glyph_at(10, 5));
+        assert_eq!(emu.registers()[0xF], 0);
+    }
+
+    #[test]
+    fn sprite_rows_wrap_on_screen_height() {
+        // LD V0, 62; LD V1, 30; LD F, V2; DRW V0, V1, 5
+        let mut emu = emu_with(&[0x60, 62, 0x61, 30, 0xF2, 0x29, 0xD0, 0x15]);
+        for _ in 0..4 {
+            emu.tick().unwrap();
+        }
+        assert_eq!(lit_pixels(&emu), zero_glyph_at(62, 30));
+    }
+
+    // LD V0, 10; LD V1, 5; LD I, 0x20A; DRW V0, V1, 1; then the scroll under test, the sprite row at 0x20A is 0x80
+    fn pixel_then_scroll(quirks: Quirks, scroll: [u8; 2]) -> Emu {
+        let mut emu = Emu::new();
+        emu.set_quirks(quirks);
+        emu.load(&[0x60, 10, 0x61, 5, 0xA2, 0x0A, 0xD0, 0x11, scroll[0], scroll[1], 0x80, 0x00]).unwrap();
+        for _ in 0..5 {
+            emu.tick().unwrap();
+        }
+        emu
+    }
+
+    #[test]
+    fn lores_scroll_enabled_moves_the_screen() {
+        // SCD 3
+        let emu = pixel_then_scroll(Quirks::default(), [0x00, 0xC3]);
+        assert_eq!(lit_pixels(&emu), vec![(10, 8)]);
+    }
+
+    #[test]
+    fn lores_scroll_disabled_is_a_no_op() {
+        let quirks = Quirks { lores_scroll_enabled: false, ..Quirks::default() };
+        for scroll in [[0x00, 0xC3], [0x00, 0xFB], [0x00, 0xFC]] {
+            let emu = pixel_then_scroll(quirks, scroll);
+            assert_eq!(lit_pixels(&emu), vec![(10, 5)]);
+        }
+    }
+
+    #[test]
+    fn core_compiles_and_ticks() {
+        // LD V0, 0x2A
+        let mut emu = emu_with(&[0x60, 0x2A]);
+        emu.tick().unwrap();
+        assert_eq!(emu.registers()[0], 0x2A);
+        assert_eq!(emu.program_counter(), START_ADDR + 2);
+    }
+
+    #[test]
+    fn first_pressed_key_is_the_lowest_index() {
+        let mut emu = Emu::new();
+        assert_eq!(emu.first_pressed_key(), None);
+        emu.keypress(0x7, true);
+        emu.keypress(0x3, true);
+        assert_eq!(emu.first_pressed_key(), Some(0x3));
+        emu.keypress(0x3, false);
+        assert_eq!(emu.first_pressed_key(), Some(0x7));
+    }
+
+    #[test]
+    fn fx29_points_i_at_the_glyph() {
+        // LD V0, 0xA; LD F, V0
+        let mut emu = emu_with(&[0x60, 0xA, 0xF0, 0x29]);
+        emu.tick().unwrap();
+        emu.tick().unwrap();
+        assert_eq!(emu.index_register(), 50);
+    }
+
+    #[test]
+    fn fx29_glyph_can_be_drawn() {
+        // LD V0, 0xA; LD F, V0; LD V1, 0; DRW V1, V1, 5
+        let mut emu = emu_with(&[0x60, 0xA, 0xF0, 0x29, 0x61, 0x00, 0xD1, 0x15]);
+        for _ in 0..4 {
+            emu.tick().unwrap();
+        }
+        // F0 90 F0 90 90
+        let a_glyph = [
+            "####",
+            "#..#",
+            "####",
+            "#..#",
+            "#..#",
+        ];
+        for (y, row) in a_glyph.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                assert_eq!(emu.get_display()[x + SCREEN_WIDTH * y], c == '#', "pixel ({}, {})", x, y);
+            }
+        }
+        assert_eq!(lit_pixels(&emu).len(), 14);
+    }
+
+    // LD V0, 1 in the last two bytes of RAM, run it so PC ends up at RAM_SIZE
+    fn emu_at_ram_end(policy: PcOverflow) -> Emu {
+        let mut emu = Emu::new();
+        emu.set_pc_overflow(policy);
+        emu.load_at(RAM_SIZE as u16 - 2, &[0x60, 0x01]).unwrap();
+        emu.set_program_counter(RAM_SIZE as u16 - 2);
+        emu.tick().unwrap();
+        assert_eq!(emu.program_counter() as usize, RAM_SIZE);
+        emu
+    }
+
+    #[test]
+    fn pc_overflow_wrap_continues_from_zero() {
+        let mut emu = emu_at_ram_end(PcOverflow::Wrap);
+        // JMP 0x200 at address 0
+        emu.load_at(0, &[0x12, 0x00]).unwrap();
+        emu.tick().unwrap();
+        assert_eq!(emu.program_counter(), START_ADDR);
+        assert!(!emu.is_halted());
+    }
+
+    #[test]
+    fn pc_overflow_halt_stops_executing() {
+        let mut emu = emu_at_ram_end(PcOverflow::Halt);
+        assert_eq!(emu.step(), Ok(Op::Nop));
+        assert!(emu.is_halted());
+        let cycles = emu.cycles();
+        emu.tick().unwrap();
+        assert_eq!(emu.cycles(), cycles);
+    }
+
+    #[test]
+    fn pc_overflow_error_is_the_default() {
+        let mut emu = emu_at_ram_end(PcOverflow::default());
+        assert_eq!(emu.tick(), Err(Chip8Error::PcOutOfBounds(RAM_SIZE as u16)));
+        assert!(!emu.is_halted());
+    }
+
+    #[test]
+    fn fx33_stores_bcd_digits() {
+        // LD V3, 254; LD I, 0x300; LD B, V3
+        let mut emu = emu_with(&[0x63, 254, 0xA3, 0x00, 0xF3, 0x33]);
+        for _ in 0..3 {
+            emu.tick().unwrap();
+        }
+        assert_eq!(&emu.ram()[0x300..0x303], &[2, 5, 4]);
+
+        // LD V3, 0; LD I, 0x300; LD B, V3
+        let mut emu = emu_with(&[0x63, 0, 0xA3, 0x00, 0xF3, 0x33]);
+        emu.load_at(0x300, &[9, 9, 9]).unwrap();
+        for _ in 0..3 {
+            emu.tick().unwrap();
+        }
+        assert_eq!(&emu.ram()[0x300..0x303], &[0, 0, 0]);
+    }
+
+    #[test]
+    fn fx33_past_the_end_of_ram_is_an_error() {
+        // LD I, 0xFFF; LD B, V0
+        let mut emu = emu_with(&[0xAF, 0xFF, 0xF0, 0x33]);
+        emu.tick().unwrap();
+        assert_eq!(emu.tick(), Err(Chip8Error::AddressOutOfBounds(0xFFF)));
+        assert_eq!(emu.ram()[0xFFF], 0);
+    }
+
+    #[test]
+    fn fx_family_uses_vx() {
+        let mut emu = emu_with(&[
+            0x65, 0x07, // LD V5, 7
+            0xF5, 0x15, // LD DT, V5
+            0xF5, 0x18, // LD ST, V5
+            0xA3, 0x00, // LD I, 0x300
+            0xF5, 0x1E, // ADD I, V5
+            0xF5, 0x33, // LD B, V5
+            0xF5, 0x55, // LD [I], V5
+            0xF5, 0x65, // LD V5, [I]
+            0xF5, 0x29, // LD F, V5
+            0xF6, 0x0A, // LD V6, K
+        ]);
+        for _ in 0..9 {
+            emu.tick().unwrap();
+        }
+        assert_eq!(emu.dt, 7);
+        assert_eq!(emu.st, 7);
+        // ADD I moved I to 0x307, then BCD of 7 got overwritten by the register store
+        assert_eq!(&emu.ram()[0x307..0x30D], &[0, 0, 0, 0, 0, 7]);
+        assert_eq!(emu.registers()[5], 7);
+        assert_eq!(emu.index_register(), 35);
+        // FX0A keeps waiting until a key goes down
+        emu.tick().unwrap();
+        assert_eq!(emu.program_counter(), START_ADDR + 18);
+        emu.keypress(0xC, true);
+        emu.tick().unwrap();
+        assert_eq!(emu.registers()[6], 0xC);
+    }
+
+    #[test]
+    fn fx55_fx65_past_the_end_of_ram_are_errors() {
+        // LD I, 0xFFF; LD [I], VF
+        let mut emu = emu_with(&[0xAF, 0xFF, 0xFF, 0x55]);
+        emu.tick().unwrap();
+        assert_eq!(emu.tick(), Err(Chip8Error::AddressOutOfBounds(0xFFF)));
+        // LD I, 0xFFF; LD VF, [I]
+        let mut emu = emu_with(&[0xAF, 0xFF, 0xFF, 0x65]);
+        emu.tick().unwrap();
+        assert_eq!(emu.tick(), Err(Chip8Error::AddressOutOfBounds(0xFFF)));
+        // a single register still fits in the last byte
+        let mut emu = emu_with(&[0xAF, 0xFF, 0xF0, 0x55]);
+        emu.tick().unwrap();
+        emu.tick().unwrap();
+    }
+
+    #[test]
+    fn step_returns_the_decoded_instruction() {
+        // LD V3, 0x1F; DRW V1, V2, 4
+        let mut emu = emu_with(&[0x63, 0x1F, 0xD1, 0x24]);
+        assert_eq!(emu.step(), Ok(Op::LoadImm { x: 3, nn: 0x1F }));
+        assert_eq!(emu.step(), Ok(Op::DrawSprite { x: 1, y: 2, n: 4 }));
+    }
+
+    #[test]
+    fn unknown_opcode_is_an_error() {
+        // SYS 0x123
+        let mut emu = emu_with(&[0x01, 0x23]);
+        assert_eq!(emu.step(), Err(Chip8Error::UnknownOpcode(0x0123)));
+        assert_eq!(emu.cycles(), 0);
+    }
+
+    #[test]
+    fn seventeen_nested_calls_overflow_the_stack() {
+        // 0x200: CALL 0x202, 0x202: CALL 0x204, ...
+        let rom: Vec<u8> = (0..17u16).flat_map(|i| (0x2000 | (START_ADDR + 2 * (i + 1))).to_be_bytes()).collect();
+        let mut emu = emu_with(&rom);
+        for _ in 0..STACK_SIZE {
+            emu.tick().unwrap();
+        }
+        assert_eq!(emu.tick(), Err(Chip8Error::StackOverflow));
+        assert_eq!(emu.sp as usize, STACK_SIZE);
+    }
+
+    #[test]
+    fn return_without_call_underflows_the_stack() {
+        // RET
+        let mut emu = emu_with(&[0x00, 0xEE]);
+        assert_eq!(emu.tick(), Err(Chip8Error::StackUnderflow));
+        assert_eq!(emu.sp, 0);
+    }
+
+    #[test]
+    fn call_and_return() {
+        // CALL 0x206; JMP 0x202; (pad); RET
+        let mut emu = emu_with(&[0x22, 0x06, 0x12, 0x02, 0x00, 0x00, 0x00, 0xEE]);
+        emu.tick().unwrap();
+        assert_eq!(emu.program_counter(), 0x206);
+        emu.tick().unwrap();
+        assert_eq!(emu.program_counter(), 0x202);
+        assert_eq!(emu.sp, 0);
+    }
+
+    #[test]
+    fn cxnn_masks_a_seeded_random_byte() {
+        // RND V2, 0x0F
+        let mut emu = emu_with(&[0xC2, 0x0F]);
+        emu.set_rng_seed(42);
+        emu.tick().unwrap();
+        let expected = StdRng::seed_from_u64(42).gen::<u8>() & 0x0F;
+        assert_eq!(emu.registers()[2], expected);
+    }
+
+    #[test]
+    fn same_seed_same_numbers() {
+        // RND V0, 0xFF; JMP 0x200
+        let rom = [0xC0, 0xFF, 0x12, 0x00];
+        let mut a = emu_with(&rom);
+        let mut b = emu_with(&rom);
+        a.set_rng_seed(7);
+        b.set_rng_seed(7);
+        for _ in 0..20 {
+            a.tick().unwrap();
+            b.tick().unwrap();
+            assert_eq!(a.registers()[0], b.registers()[0]);
+        }
+        // reset starts the seeded sequence over
+        let first = StdRng::seed_from_u64(7).gen::<u8>();
+        a.reset();
+        a.load(&rom).unwrap();
+        a.tick().unwrap();
+        assert_eq!(a.registers()[0], first);
+    }
+
+    #[test]
+    fn beeps_while_the_sound_timer_runs() {
+        // LD V0, 2; LD ST, V0
+        let mut emu = emu_with(&[0x60, 0x02, 0xF0, 0x18]);
+        assert!(!emu.is_beeping());
+        emu.tick().unwrap();
+        emu.tick().unwrap();
+        assert!(emu.is_beeping());
+        emu.tick_timers();
+        assert!(emu.is_beeping());
+        emu.tick_timers();
+        assert!(!emu.is_beeping());
+        emu.tick_timers();
+        assert!(!emu.is_beeping());
+    }
+
+    #[test]
+    fn bnnn_target_is_masked_to_12_bits() {
+        // LD V0, 0xFF; JMP V0, 0xFFF
+        let mut emu = emu_with(&[0x60, 0xFF, 0xBF, 0xFF]);
+        emu.tick().unwrap();
+        emu.tick().unwrap();
+        assert_eq!(emu.program_counter(), (0xFF + 0xFFF) & 0xFFF);
+        assert!(emu.program_counter() <= 0xFFF);
+    }
+
+    #[test]
+    fn font_protection_rejects_stores_into_the_font() {
+        // LD I, 0x010; LD [I], V3
+        let rom = [0xA0, 0x10, 0xF3, 0x55];
+        let mut emu = emu_with(&rom);
+        emu.set_font_protection(true);
+        emu.tick().unwrap();
+        assert_eq!(emu.tick(), Err(Chip8Error::FontWrite(0x010)));
+        assert!(emu.verify_font());
+
+        // off by default
+        let mut emu = emu_with(&rom);
+        emu.tick().unwrap();
+        emu.tick().unwrap();
+        assert!(!emu.verify_font());
+    }
+
+    #[test]
+    fn load_rejects_roms_that_do_not_fit() {
+        let mut emu = Emu::new();
+        assert_eq!(
+            emu.load(&[0; 4000]),
+            Err(Chip8Error::RomTooLarge { size: 4000, max: RAM_SIZE - START_ADDR as usize })
+        );
+        // the largest ROM that fits
+        emu.load(&[0; RAM_SIZE - START_ADDR as usize]).unwrap();
+    }
+
+    #[test]
+    fn font_is_intact_after_new_reset_and_a_run() {
+        let mut emu = emu_with(&[0x70, 0x01, 0x12, 0x00]);
+        assert!(emu.verify_font());
+        for _ in 0..100 {
+            emu.tick().unwrap();
+        }
+        assert!(emu.verify_font());
+        // clobber the font, reset puts it back
+        emu.load_at(0, &[0; FONTSET_SIZE]).unwrap();
+        assert!(!emu.verify_font());
+        emu.reset();
+        assert!(emu.verify_font());
+    }
+
+    #[test]
+    fn accessors_show_the_loaded_rom() {
+        let rom = [0x60, 0x2A, 0xA3, 0x21];
+        let mut emu = emu_with(&rom);
+        let start = START_ADDR as usize;
+        assert_eq!(&emu.ram()[start..start + rom.len()], &rom);
+        assert_eq!(emu.ram().len(), RAM_SIZE);
+        emu.tick().unwrap();
+        emu.tick().unwrap();
+        assert_eq!(emu.registers()[0], 0x2A);
+        assert_eq!(emu.registers().len(), NUM_REGS);
+        assert_eq!(emu.program_counter(), START_ADDR + 4);
+        assert_eq!(emu.index_register(), 0x321);
+    }
+
+    #[test]
+    fn delay_timer_counts_down_at_timer_hz() {
+        // LD V0, TIMER_HZ; LD DT, V0
+        let mut emu = emu_with(&[0x60, TIMER_HZ as u8, 0xF0, 0x15]);
+        emu.tick().unwrap();
+        emu.tick().unwrap();
+        // one second worth of frames empties a timer loaded with TIMER_HZ
+        for _ in 0..TIMER_HZ - 1 {
+            emu.tick_timers();
+        }
+        assert_eq!(emu.dt, 1);
+        emu.tick_timers();
+        assert_eq!(emu.dt, 0);
+    }
+
+    #[test]
+    fn cpu_rate_is_spread_over_timer_frames() {
+        let mut emu = Emu::new();
+        assert_eq!(emu.cpu_hz(), DEFAULT_CPU_HZ);
+        assert_eq!(emu.ticks_per_frame(), (DEFAULT_CPU_HZ / TIMER_HZ) as usize);
+        emu.set_cpu_hz(TIMER_HZ * 25);
+        assert_eq!(emu.ticks_per_frame(), 25);
+        // 1000Hz is 16.67 instructions per frame, which rounds up to 17
+        emu.set_cpu_hz(1000);
+        assert_eq!(emu.ticks_per_frame(), 17);
+        emu.set_cpu_hz(TIMER_HZ * 25 + TIMER_HZ / 2 - 1);
+        assert_eq!(emu.ticks_per_frame(), 25);
+        // never less than one instruction per frame
+        emu.set_cpu_hz(1);
+        assert_eq!(emu.ticks_per_frame(), 1);
+    }
+
+    #[test]
+    fn out_of_range_keypress_is_ignored() {
+        let mut emu = Emu::new();
+        emu.keypress(16, true);
+        emu.keypress(usize::MAX, true);
+        assert_eq!(emu.first_pressed_key(), None);
+        assert_eq!(emu.input_state(), InputState::new());
+        assert_eq!(emu.diff_state(&Emu::new()), None);
+    }
+
+    #[test]
+    fn skip_on_a_key_past_0xf_does_not_panic() {
+        // LD V0, 0x10; SKP V0; SKNP V0
+        let mut emu = emu_with(&[0x60, 0x10, 0xE0, 0x9E, 0xE0, 0xA1]);
+        emu.tick().unwrap();
+        emu.tick().unwrap();
+        assert_eq!(emu.program_counter(), START_ADDR + 4);
+        emu.tick().unwrap();
+        assert_eq!(emu.program_counter(), START_ADDR + 8);
+    }
+
+    #[test]
+    fn hires_changes_the_width_used_by_dxyn() {
+        // LD V0, 100; LD V1, 40; LD I, 0x20C; DRW V0, V1, 1; HIGH; JMP 0x200; sprite row 0x80 at 0x20C
+        let rom = [0x60, 100, 0x61, 40, 0xA2, 0x0C, 0xD0, 0x11, 0x00, 0xFF, 0x12, 0x00, 0x80, 0x00];
+        let mut emu = emu_with(&rom);
+        for _ in 0..4 {
+            emu.tick().unwrap();
+        }
+        // lores wraps 100 to 36 and 40 to 8
+        assert_eq!(lit_pixels(&emu), vec![(36, 8)]);
+        assert!(emu.get_display()[36 + SCREEN_WIDTH * 8]);
+
+        emu.tick().unwrap();
+        assert!(emu.is_hires());
+        assert_eq!(emu.screen_width(), HIRES_WIDTH);
+        assert_eq!(emu.get_display().len(), HIRES_WIDTH * HIRES_HEIGHT);
+        assert!(lit_pixels(&emu).is_empty());
+        for _ in 0..5 {
+            emu.tick().unwrap();
+        }
+        assert_eq!(lit_pixels(&emu), vec![(100, 40)]);
+        assert!(emu.get_display()[100 + HIRES_WIDTH * 40]);
+    }
+
+    #[test]
+    fn hires_dxy0_draws_a_16x16_sprite() {
+        let mut rom = vec![
+            0x00, 0xFF, // HIGH
+            0x60, 120, // LD V0, 120
+            0x61, 2, // LD V1, 2
+            0xA2, 0x0C, // LD I, 0x20C
+            0xD0, 0x10, // DRW V0, V1, 0
+            0x12, 0x0A, // JMP 0x20A
+        ];
+        // a 16x16 outline, two bytes per row
+        for row in 0..16 {
+            rom.extend_from_slice(if row == 0 || row == 15 { &[0xFF, 0xFF] } else { &[0x80, 0x01] });
+        }
+        let mut emu = emu_with(&rom);
+        for _ in 0..5 {
+            emu.tick().unwrap();
+        }
+        let mut expected = Vec::new();
+        for y in 2..18 {
+            for x in 120..136 {
+                if y == 2 || y == 17 || x == 120 || x == 135 {
+                    // columns past 127 wrap to the left edge
+                    expected.push((x % HIRES_WIDTH, y));
+                }
+            }
+        }
+        expected.sort_by_key(|(x, y)| (*y, *x));
+        assert_eq!(lit_pixels(&emu), expected);
+        assert_eq!(emu.registers()[0xF], 0);
+    }
+
+    #[test]
+    fn lores_dxy0_draws_nothing() {
+        // LD I, 0x000; DRW V0, V0, 0
+        let mut emu = emu_with(&[0xA0, 0x00, 0xD0, 0x00]);
+        emu.tick().unwrap();
+        emu.tick().unwrap();
+        assert!(lit_pixels(&emu).is_empty());
+    }
+
+    #[test]
+    fn sprite_past_the_end_of_ram_is_an_error() {
+        // LD I, 0xFFE; DRW V0, V0, 5
+        let mut emu = emu_with(&[0xAF, 0xFE, 0xD0, 0x05]);
+        emu.tick().unwrap();
+        assert_eq!(emu.tick(), Err(Chip8Error::AddressOutOfBounds(0xFFE)));
+    }
+
+    #[test]
+    fn scroll_moves_a_pixel_and_clears_where_it_was() {
+        for (scroll, moved_to) in [([0x00, 0xC3], (10, 8)), ([0x00, 0xFB], (14, 5)), ([0x00, 0xFC], (6, 5))] {
+            let emu = pixel_then_scroll(Quirks::default(), scroll);
+            assert_eq!(lit_pixels(&emu), vec![moved_to]);
+            assert!(!emu.get_display()[10 + SCREEN_WIDTH * 5]);
+        }
+    }
+
+    #[test]
+    fn scroll_drops_pixels_off_the_edge() {
+        // LD V0, 62; LD V1, 31; LD I, 0x20C; DRW V0, V1, 1; SCR; SCD 1; sprite row 0xC0 at 0x20C
+        let mut emu = emu_with(&[0x60, 62, 0x61, 31, 0xA2, 0x0C, 0xD0, 0x11, 0x00, 0xFB, 0x00, 0xC1, 0xC0, 0x00]);
+        for _ in 0..5 {
+            emu.tick().unwrap();
+        }
+        // scrolled right by 4, the pixels at 62 and 63 are gone rather than wrapped
+        assert!(lit_pixels(&emu).is_empty());
+
+        // LD V0, 10; LD V1, 31; LD I, 0x20C; DRW V0, V1, 1; SCD 1
+        let mut emu = emu_with(&[0x60, 10, 0x61, 31, 0xA2, 0x0C, 0xD0, 0x11, 0x00, 0xC1, 0x00, 0x00, 0xC0, 0x00]);
+        for _ in 0..5 {
+            emu.tick().unwrap();
+        }
+        assert!(lit_pixels(&emu).is_empty());
+    }
+
+    #[test]
+    fn scroll_uses_the_hires_screen() {
+        // HIGH; LD V0, 100; LD V1, 40; LD I, 0x20E; DRW V0, V1, 1; SCD 5; sprite row 0x80 at 0x20E
+        let mut emu = emu_with(&[0x00, 0xFF, 0x60, 100, 0x61, 40, 0xA2, 0x0E, 0xD0, 0x11, 0x00, 0xC5, 0x00, 0x00, 0x80, 0x00]);
+        for _ in 0..6 {
+            emu.tick().unwrap();
+        }
+        assert_eq!(lit_pixels(&emu), vec![(100, 45)]);
+    }
+
+    #[test]
+    fn load_at_a_non_standard_address() {
+        let mut emu = Emu::new();
+        // LD V0, 0x42 at the ETI-660 start address
+        emu.load_at(0x600, &[0x60, 0x42]).unwrap();
+        assert_eq!(&emu.ram()[0x600..0x602], &[0x60, 0x42]);
+        assert!(emu.ram()[START_ADDR as usize..0x600].iter().all(|b| *b == 0));
+        emu.set_program_counter(0x600);
+        emu.tick().unwrap();
+        assert_eq!(emu.registers()[0], 0x42);
+        assert_eq!(emu.program_counter(), 0x602);
+    }
+
+    #[test]
+    fn load_at_checks_the_end_of_ram() {
+        let mut emu = Emu::new();
+        emu.load_at(RAM_SIZE as u16 - 2, &[1, 2]).unwrap();
+        assert_eq!(emu.load_at(RAM_SIZE as u16 - 1, &[1, 2]), Err(Chip8Error::RomTooLarge { size: 2, max: 1 }));
+        assert_eq!(emu.load_at(0x2000, &[1]), Err(Chip8Error::RomTooLarge { size: 1, max: 0 }));
+    }
+
+    #[test]
+    fn fetch_near_the_top_of_ram_is_an_error() {
+        for pc in [RAM_SIZE as u16 - 1, RAM_SIZE as u16, 0xFFFF] {
+            let mut emu = Emu::new();
+            emu.set_program_counter(pc);
+            assert_eq!(emu.tick(), Err(Chip8Error::PcOutOfBounds(pc)));
+            assert_eq!(emu.program_counter(), pc);
+        }
+    }
+
+    #[test]
+    fn reset_clears_the_stack_and_timers() {
+        let rom = [
+            0x60, 0x30, // 0x200 LD V0, 0x30
+            0xF0, 0x15, // 0x202 LD DT, V0
+            0xF0, 0x18, // 0x204 LD ST, V0
+            0x00, 0xFF, // 0x206 HIGH
+            0x22, 0x0C, // 0x208 CALL 0x20C
+            0x00, 0x00,
+            0xD0, 0x05, // 0x20C DRW V0, V0, 5
+        ];
+        let mut emu = emu_with(&rom);
+        for _ in 0..6 {
+            emu.tick().unwrap();
+        }
+        assert_eq!(emu.sp, 1);
+        assert!(emu.is_beeping());
+
+        emu.reset();
+        emu.load(&rom).unwrap();
+        assert_eq!(emu.sp, 0);
+        assert_eq!(emu.dt, 0);
+        assert_eq!(emu.st, 0);
+        assert!(!emu.is_hires());
+        assert_eq!(emu.program_counter(), START_ADDR);
+        assert_eq!(emu.cycles(), 0);
+        assert!(lit_pixels(&emu).is_empty());
+        assert_eq!(emu.diff_state(&emu_with(&rom)), None);
     }
 }
@@ -0,0 +1,146 @@
+// a decoded CHIP-8 instruction, x and y are register indices and nn/nnn are the immediate values
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    // 0000
+    Nop,
+    // 00CN
+    ScrollDown(u8),
+    // 00E0
+    ClearScreen,
+    // 00EE
+    Return,
+    // 00FB
+    ScrollRight,
+    // 00FC
+    ScrollLeft,
+    // 00FE
+    LowRes,
+    // 00FF
+    HighRes,
+    // 1NNN
+    Jump(u16),
+    // 2NNN
+    Call(u16),
+    // 3XNN
+    SkipEqImm { x: u8, nn: u8 },
+    // 4XNN
+    SkipNeImm { x: u8, nn: u8 },
+    // 5XY0
+    SkipEqReg { x: u8, y: u8 },
+    // 6XNN
+    LoadImm { x: u8, nn: u8 },
+    // 7XNN
+    AddImm { x: u8, nn: u8 },
+    // 8XY0
+    Move { x: u8, y: u8 },
+    // 8XY1
+    Or { x: u8, y: u8 },
+    // 8XY2
+    And { x: u8, y: u8 },
+    // 8XY3
+    Xor { x: u8, y: u8 },
+    // 8XY4
+    AddReg { x: u8, y: u8 },
+    // 8XY5
+    SubReg { x: u8, y: u8 },
+    // 8XY6
+    ShiftRight { x: u8, y: u8 },
+    // 8XY7
+    SubNeg { x: u8, y: u8 },
+    // 8XYE
+    ShiftLeft { x: u8, y: u8 },
+    // 9XY0
+    SkipNeReg { x: u8, y: u8 },
+    // ANNN
+    LoadI(u16),
+    // BNNN
+    JumpV0(u16),
+    // CXNN
+    Random { x: u8, nn: u8 },
+    // DXYN
+    DrawSprite { x: u8, y: u8, n: u8 },
+    // EX9E
+    SkipKeyPressed { x: u8 },
+    // EXA1
+    SkipKeyNotPressed { x: u8 },
+    // FX07
+    LoadDelay { x: u8 },
+    // FX0A
+    WaitKey { x: u8 },
+    // FX15
+    SetDelay { x: u8 },
+    // FX18
+    SetSound { x: u8 },
+    // FX1E
+    AddI { x: u8 },
+    // FX29
+    LoadFont { x: u8 },
+    // FX33
+    Bcd { x: u8 },
+    // FX55
+    StoreRegs { x: u8 },
+    // FX65
+    LoadRegs { x: u8 },
+    // anything the core doesn't know how to execute
+    Unknown(u16),
+}
+
+impl Op {
+    pub fn decode(op: u16) -> Op {
+        // opcodes have 4 hex digits, to decode the opcode, we need to separate each digit
+        let digit1 = (op & 0xF000) >> 12; // shift by 3 hex digits or 12 bits
+        let digit2 = (op & 0x0F00) >> 8;  // shift by 2 hex digits or 8 bits
+        let digit3 = (op & 0x00F0) >> 4;  // shift by 1 hex digits or 4 bits
+        let digit4 = op & 0x000F;
+
+        let x = digit2 as u8;
+        let y = digit3 as u8;
+        let n = digit4 as u8;
+        let nn = (op & 0xFF) as u8;
+        let nnn = op & 0xFFF;
+        // match statement to sepcify match pattern for our opcode
+        match (digit1, digit2, digit3, digit4) {
+            (0, 0, 0, 0) => Op::Nop,
+            (0, 0, 0xC, _) => Op::ScrollDown(n),
+            (0, 0, 0xE, 0) => Op::ClearScreen,
+            (0, 0, 0xE, 0xE) => Op::Return,
+            (0, 0, 0xF, 0xB) => Op::ScrollRight,
+            (0, 0, 0xF, 0xC) => Op::ScrollLeft,
+            (0, 0, 0xF, 0xE) => Op::LowRes,
+            (0, 0, 0xF, 0xF) => Op::HighRes,
+            (1, _, _, _) => Op::Jump(nnn),
+            (2, _, _, _) => Op::Call(nnn),
+            (3, _, _, _) => Op::SkipEqImm { x, nn },
+            (4, _, _, _) => Op::SkipNeImm { x, nn },
+            (5, _, _, 0) => Op::SkipEqReg { x, y },
+            (6, _, _, _) => Op::LoadImm { x, nn },
+            (7, _, _, _) => Op::AddImm { x, nn },
+            (8, _, _, 0) => Op::Move { x, y },
+            (8, _, _, 1) => Op::Or { x, y },
+            (8, _, _, 2) => Op::And { x, y },
+            (8, _, _, 3) => Op::Xor { x, y },
+            (8, _, _, 4) => Op::AddReg { x, y },
+            (8, _, _, 5) => Op::SubReg { x, y },
+            (8, _, _, 6) => Op::ShiftRight { x, y },
+            (8, _, _, 7) => Op::SubNeg { x, y },
+            (8, _, _, 0xE) => Op::ShiftLeft { x, y },
+            (9, _, _, 0) => Op::SkipNeReg { x, y },
+            (0xA, _, _, _) => Op::LoadI(nnn),
+            (0xB, _, _, _) => Op::JumpV0(nnn),
+            (0xC, _, _, _) => Op::Random { x, nn },
+            (0xD, _, _, _) => Op::DrawSprite { x, y, n },
+            (0xE, _, 9, 0xE) => Op::SkipKeyPressed { x },
+            (0xE, _, 0xA, 1) => Op::SkipKeyNotPressed { x },
+            (0xF, _, 0, 7) => Op::LoadDelay { x },
+            (0xF, _, 0, 0xA) => Op::WaitKey { x },
+            (0xF, _, 1, 5) => Op::SetDelay { x },
+            (0xF, _, 1, 8) => Op::SetSound { x },
+            (0xF, _, 1, 0xE) => Op::AddI { x },
+            (0xF, _, 2, 9) => Op::LoadFont { x },
+            (0xF, _, 3, 3) => Op::Bcd { x },
+            (0xF, _, 5, 5) => Op::StoreRegs { x },
+            (0xF, _, 6, 5) => Op::LoadRegs { x },
+            (_, _, _, _) => Op::Unknown(op),
+        }
+    }
+}
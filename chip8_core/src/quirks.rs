@@ -0,0 +1,125 @@
+use crate::Emu;
+
+// which key FX0A picks when several keys are down at once
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KeyWaitPolicy {
+    // the pressed key with the lowest index
+    #[default]
+    LowestIndex,
+    // the pressed key that went down last
+    MostRecent,
+}
+
+// behaviors that differ between CHIP-8 interpreters, the defaults match what this core has always done
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    // 8XY6/8XYE shift VY and store the result in VX (original COSMAC VIP) instead of shifting VX in place
+    pub shift_uses_vy: bool,
+    // FX55/FX65 leave I pointing past the last register copied (original COSMAC VIP) instead of unchanged
+    pub mem_increments_i: bool,
+    pub key_wait: KeyWaitPolicy,
+    // 00CN/00FB/00FC also scroll in lores (SUPER-CHIP 1.1), when off they do nothing until 00FF (SUPER-CHIP 1.0)
+    pub lores_scroll_enabled: bool,
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self {
+            shift_uses_vy: false,
+            mem_increments_i: false,
+            key_wait: KeyWaitPolicy::default(),
+            lores_scroll_enabled: true,
+        }
+    }
+}
+
+impl Emu {
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(quirks: Quirks, rom: &[u8], ticks: usize) -> Emu {
+        let mut emu = Emu::new();
+        emu.set_quirks(quirks);
+        emu.load(rom).unwrap();
+        for _ in 0..ticks {
+            emu.tick().unwrap();
+        }
+        emu
+    }
+
+    // LD V0, 0x10; LD V1, 0x81; SHR V0, V1; LD V2, 0x10; SHL V2, V1
+    const SHIFTS: [u8; 10] = [0x60, 0x10, 0x61, 0x81, 0x80, 0x16, 0x62, 0x10, 0x82, 0x1E];
+
+    #[test]
+    fn shifts_vx_in_place_by_default() {
+        let emu = run(Quirks::default(), &SHIFTS, 3);
+        assert_eq!(emu.registers()[0], 0x08);
+        assert_eq!(emu.registers()[0xF], 0);
+        let emu = run(Quirks::default(), &SHIFTS, 5);
+        assert_eq!(emu.registers()[2], 0x20);
+        assert_eq!(emu.registers()[0xF], 0);
+    }
+
+    #[test]
+    fn shift_uses_vy() {
+        let quirks = Quirks { shift_uses_vy: true, ..Quirks::default() };
+        let emu = run(quirks, &SHIFTS, 3);
+        assert_eq!(emu.registers()[0], 0x40);
+        assert_eq!(emu.registers()[0xF], 1);
+        let emu = run(quirks, &SHIFTS, 5);
+        assert_eq!(emu.registers()[2], 0x02);
+        assert_eq!(emu.registers()[0xF], 1);
+    }
+
+    // LD I, 0x300; LD [I], V2; LD V2, [I]
+    const STORE_LOAD: [u8; 6] = [0xA3, 0x00, 0xF2, 0x55, 0xF2, 0x65];
+
+    #[test]
+    fn store_and_load_leave_i_alone_by_default() {
+        let emu = run(Quirks::default(), &STORE_LOAD, 3);
+        assert_eq!(emu.index_register(), 0x300);
+    }
+
+    #[test]
+    fn store_and_load_increment_i() {
+        let quirks = Quirks { mem_increments_i: true, ..Quirks::default() };
+        let emu = run(quirks, &STORE_LOAD, 2);
+        assert_eq!(emu.index_register(), 0x303);
+        let emu = run(quirks, &STORE_LOAD, 3);
+        assert_eq!(emu.index_register(), 0x306);
+    }
+
+    // press the keys in order, then run LD V0, K
+    fn wait_key_with(policy: KeyWaitPolicy, presses: &[usize]) -> u8 {
+        let mut emu = Emu::new();
+        emu.set_quirks(Quirks { key_wait: policy, ..Quirks::default() });
+        emu.load(&[0xF0, 0x0A]).unwrap();
+        for key in presses {
+            emu.keypress(*key, true);
+        }
+        emu.tick().unwrap();
+        emu.registers()[0]
+    }
+
+    #[test]
+    fn fx0a_picks_the_lowest_key_by_default() {
+        assert_eq!(wait_key_with(KeyWaitPolicy::default(), &[0x7, 0x3]), 0x3);
+        assert_eq!(wait_key_with(KeyWaitPolicy::default(), &[0x3, 0x7]), 0x3);
+    }
+
+    #[test]
+    fn fx0a_picks_the_most_recent_key() {
+        assert_eq!(wait_key_with(KeyWaitPolicy::MostRecent, &[0x7, 0x3]), 0x3);
+        assert_eq!(wait_key_with(KeyWaitPolicy::MostRecent, &[0x3, 0x7]), 0x7);
+    }
+}
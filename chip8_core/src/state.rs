@@ -0,0 +1,274 @@
+use crate::{Chip8Error, Emu, NUM_KEYS, NUM_REGS, HIRES_HEIGHT, HIRES_WIDTH, RAM_SIZE, STACK_SIZE};
+
+// save states start with this tag followed by a version byte, so other files and future formats get rejected
+const STATE_MAGIC: &[u8; 4] = b"C8ST";
+const STATE_VERSION: u8 = 2;
+// magic + version + pc + ram + screen + hires + v_reg + i_reg + sp + stack + keys + dt + st
+const STATE_LEN: usize = 4 + 1 + 2 + RAM_SIZE + HIRES_WIDTH * HIRES_HEIGHT + 1 + NUM_REGS + 2 + 2 + STACK_SIZE * 2 + NUM_KEYS + 2;
+
+// FNV-1a constants, used instead of std's DefaultHasher so the hash stays the same across rust versions
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+// first field that differs between two machines, indices are into the matching array
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateDiff {
+    Pc,
+    Reg(usize),
+    IReg,
+    Sp,
+    Stack(usize),
+    Dt,
+    St,
+    Ram(usize),
+    Hires,
+    Screen(usize),
+    Key(usize),
+}
+
+// index of the first element that differs between two equally sized arrays
+fn first_diff<T: PartialEq>(a: &[T], b: &[T]) -> Option<usize> {
+    a.iter().zip(b.iter()).position(|(x, y)| x != y)
+}
+
+// reads fields back in the order save_state wrote them, the length is checked up front
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> &'a [u8] {
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        bytes
+    }
+
+    fn u8(&mut self) -> u8 {
+        self.bytes(1)[0]
+    }
+
+    fn u16(&mut self) -> u16 {
+        let bytes = self.bytes(2);
+        u16::from_be_bytes([bytes[0], bytes[1]])
+    }
+}
+
+struct Fnv(u64);
+
+impl Fnv {
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= *b as u64;
+            self.0 = self.0.wrapping_mul(FNV_PRIME);
+        }
+    }
+}
+
+impl Emu {
+    // compare against another machine and report the first field that doesn't match,
+    // checked in the order: PC, V registers, I, SP, stack, timers, RAM, resolution, screen, keys
+    pub fn diff_state(&self, other: &Emu) -> Option<StateDiff> {
+        if self.pc != other.pc {
+            return Some(StateDiff::Pc);
+        }
+        if let Some(i) = first_diff(&self.v_reg, &other.v_reg) {
+            return Some(StateDiff::Reg(i));
+        }
+        if self.i_reg != other.i_reg {
+            return Some(StateDiff::IReg);
+        }
+        if self.sp != other.sp {
+            return Some(StateDiff::Sp);
+        }
+        if let Some(i) = first_diff(&self.stack, &other.stack) {
+            return Some(StateDiff::Stack(i));
+        }
+        if self.dt != other.dt {
+            return Some(StateDiff::Dt);
+        }
+        if self.st != other.st {
+            return Some(StateDiff::St);
+        }
+        if let Some(i) = first_diff(&self.ram, &other.ram) {
+            return Some(StateDiff::Ram(i));
+        }
+        if self.hires != other.hires {
+            return Some(StateDiff::Hires);
+        }
+        if let Some(i) = first_diff(&self.screen, &other.screen) {
+            return Some(StateDiff::Screen(i));
+        }
+        first_diff(&self.keys, &other.keys).map(StateDiff::Key)
+    }
+
+    // snapshot the whole machine into a versioned blob that load_state can restore
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(STATE_LEN);
+        data.extend_from_slice(STATE_MAGIC);
+        data.push(STATE_VERSION);
+        data.extend_from_slice(&self.pc.to_be_bytes());
+        data.extend_from_slice(&self.ram);
+        data.extend(self.screen.iter().map(|pixel| *pixel as u8));
+        data.push(self.hires as u8);
+        data.extend_from_slice(&self.v_reg);
+        data.extend_from_slice(&self.i_reg.to_be_bytes());
+        data.extend_from_slice(&self.sp.to_be_bytes());
+        for addr in self.stack.iter() {
+            data.extend_from_slice(&addr.to_be_bytes());
+        }
+        data.extend(self.keys.iter().map(|key| *key as u8));
+        data.push(self.dt);
+        data.push(self.st);
+        data
+    }
+
+    // restore a snapshot from save_state, the machine is left untouched if the blob is rejected
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), Chip8Error> {
+        if data.len() < STATE_MAGIC.len() + 1 || &data[..STATE_MAGIC.len()] != STATE_MAGIC {
+            return Err(Chip8Error::BadStateMagic);
+        }
+        let version = data[STATE_MAGIC.len()];
+        if version != STATE_VERSION {
+            return Err(Chip8Error::UnsupportedStateVersion(version));
+        }
+        if data.len() != STATE_LEN {
+            return Err(Chip8Error::BadStateLength { expected: STATE_LEN, found: data.len() });
+        }
+
+        let mut reader = Reader { data, pos: STATE_MAGIC.len() + 1 };
+        let pc = reader.u16();
+        let ram = reader.bytes(RAM_SIZE);
+        let screen = reader.bytes(HIRES_WIDTH * HIRES_HEIGHT);
+        let hires = reader.u8() != 0;
+        let v_reg = reader.bytes(NUM_REGS);
+        let i_reg = reader.u16();
+        let sp = reader.u16();
+        // an out of range stack pointer would make the next CALL/RET index past the stack
+        if sp as usize > STACK_SIZE {
+            return Err(Chip8Error::CorruptState);
+        }
+
+        self.pc = pc;
+        self.ram.copy_from_slice(ram);
+        for (pixel, byte) in self.screen.iter_mut().zip(screen) {
+            *pixel = *byte != 0;
+        }
+        self.hires = hires;
+        self.v_reg.copy_from_slice(v_reg);
+        self.i_reg = i_reg;
+        self.sp = sp;
+        for addr in self.stack.iter_mut() {
+            *addr = reader.u16();
+        }
+        for key in self.keys.iter_mut() {
+            *key = reader.u8() != 0;
+        }
+        self.dt = reader.u8();
+        self.st = reader.u8();
+        // the snapshot was taken from a running machine
+        self.halted = false;
+        Ok(())
+    }
+
+    // hash of the whole machine state, two emulators with the same hash are (almost certainly) identical
+    pub fn state_hash(&self) -> u64 {
+        let mut h = Fnv(FNV_OFFSET);
+        h.write(&self.pc.to_be_bytes());
+        h.write(&self.ram);
+        for pixel in self.screen.iter() {
+            h.write(&[*pixel as u8]);
+        }
+        h.write(&[self.hires as u8]);
+        h.write(&self.v_reg);
+        h.write(&self.i_reg.to_be_bytes());
+        h.write(&self.sp.to_be_bytes());
+        for addr in self.stack.iter() {
+            h.write(&addr.to_be_bytes());
+        }
+        for key in self.keys.iter() {
+            h.write(&[*key as u8]);
+        }
+        h.write(&[self.dt, self.st]);
+        h.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_state_reports_the_register_that_differs() {
+        let a = Emu::new();
+        let mut b = Emu::new();
+        // nothing else differs, so a diff that skipped the registers would report None
+        b.v_reg[5] = 1;
+        assert_eq!(a.diff_state(&b), Some(StateDiff::Reg(5)));
+    }
+
+    #[test]
+    fn identical_machines_have_no_diff() {
+        let a = Emu::new();
+        let b = Emu::new();
+        assert_eq!(a.diff_state(&b), None);
+        assert_eq!(a.state_hash(), b.state_hash());
+    }
+
+    // counts V0 up, draws it and keeps CALLing into a subroutine so PC, the stack, I and the screen all move
+    const COUNTER: [u8; 16] = [
+        0x70, 0x01, // 0x200 ADD V0, 1
+        0xF0, 0x29, // 0x202 LD F, V0
+        0xD1, 0x15, // 0x204 DRW V1, V1, 5
+        0xF0, 0x15, // 0x206 LD DT, V0
+        0x22, 0x0C, // 0x208 CALL 0x20C
+        0x12, 0x00, // 0x20A JMP 0x200
+        0x00, 0xEE, // 0x20C RET
+        0x00, 0x00,
+    ];
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let mut emu = Emu::new();
+        emu.load(&COUNTER).unwrap();
+        for _ in 0..25 {
+            emu.tick().unwrap();
+        }
+        emu.keypress(0x9, true);
+        let snapshot = emu.save_state();
+        let hash = emu.state_hash();
+
+        for _ in 0..40 {
+            emu.tick().unwrap();
+        }
+        emu.keypress(0x9, false);
+        assert_ne!(emu.state_hash(), hash);
+
+        emu.load_state(&snapshot).unwrap();
+        assert_eq!(emu.state_hash(), hash);
+        assert_eq!(emu.save_state(), snapshot);
+
+        // a fresh machine restored from the snapshot matches as well
+        let mut other = Emu::new();
+        other.load_state(&snapshot).unwrap();
+        assert_eq!(other.diff_state(&emu), None);
+    }
+
+    #[test]
+    fn rejects_bad_snapshots() {
+        let mut emu = Emu::new();
+        let snapshot = emu.save_state();
+
+        assert_eq!(emu.load_state(b"nope"), Err(Chip8Error::BadStateMagic));
+
+        let mut wrong_version = snapshot.clone();
+        wrong_version[STATE_MAGIC.len()] = 99;
+        assert_eq!(emu.load_state(&wrong_version), Err(Chip8Error::UnsupportedStateVersion(99)));
+
+        let short = &snapshot[..snapshot.len() - 1];
+        assert_eq!(
+            emu.load_state(short),
+            Err(Chip8Error::BadStateLength { expected: STATE_LEN, found: STATE_LEN - 1 })
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use std::fmt;
+
+use crate::{Chip8Error, Emu};
+
+// one executed instruction: the address it was fetched from and the raw opcode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceEntry {
+    pub pc: u16,
+    pub op: u16,
+}
+
+// written as "PPPP OOOO" in hex, one entry per line
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04X} {:04X}", self.pc, self.op)
+    }
+}
+
+impl TraceEntry {
+    // parse a line written by Display, returns None for anything else
+    pub fn parse(line: &str) -> Option<TraceEntry> {
+        let mut parts = line.split_whitespace();
+        let pc = u16::from_str_radix(parts.next()?, 16).ok()?;
+        let op = u16::from_str_radix(parts.next()?, 16).ok()?;
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(TraceEntry { pc, op })
+    }
+}
+
+// where a replay stopped following the recorded trace
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceMismatch {
+    // the instruction executed at this step isn't the recorded one
+    Diverged { step: usize, expected: TraceEntry, found: TraceEntry },
+    // the machine errored (or halted) before reaching the end of the trace
+    Stopped { step: usize, error: Option<Chip8Error> },
+}
+
+impl Emu {
+    // the instruction executed by the last successful tick/step
+    pub fn last_instruction(&self) -> Option<TraceEntry> {
+        self.last_instruction
+    }
+
+    // run up to `steps` instructions and record each one, stops early if the machine halts
+    pub fn record_trace(&mut self, steps: usize) -> Result<Vec<TraceEntry>, Chip8Error> {
+        let mut trace = Vec::with_capacity(steps);
+        for _ in 0..steps {
+            self.step()?;
+            if self.halted {
+                break;
+            }
+            if let Some(entry) = self.last_instruction {
+                trace.push(entry);
+            }
+        }
+        Ok(trace)
+    }
+
+    // re-run the machine against a recorded trace and report the first step that doesn't match
+    pub fn verify_trace(&mut self, trace: &[TraceEntry]) -> Result<(), TraceMismatch> {
+        for (step, expected) in trace.iter().enumerate() {
+            if let Err(e) = self.step() {
+                return Err(TraceMismatch::Stopped { step, error: Some(e) });
+            }
+            if self.halted {
+                return Err(TraceMismatch::Stopped { step, error: None });
+            }
+            // step succeeded and didn't halt, so an instruction was executed
+            let found = self.last_instruction.unwrap();
+            if found != *expected {
+                return Err(TraceMismatch::Diverged { step, expected: *expected, found });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ADD V0, 1; SE V0, 5; JMP 0x200; JMP 0x206
+    const COUNT_TO_FIVE: [u8; 8] = [0x70, 0x01, 0x30, 0x05, 0x12, 0x00, 0x12, 0x06];
+
+    fn emu() -> Emu {
+        let mut emu = Emu::new();
+        emu.load(&COUNT_TO_FIVE).unwrap();
+        emu
+    }
+
+    #[test]
+    fn unchanged_run_matches_its_own_trace() {
+        let trace = emu().record_trace(20).unwrap();
+        assert_eq!(trace.len(), 20);
+        assert_eq!(trace[0], TraceEntry { pc: 0x200, op: 0x7001 });
+        assert_eq!(emu().verify_trace(&trace), Ok(()));
+    }
+
+    #[test]
+    fn reports_the_first_mismatch() {
+        let mut trace = emu().record_trace(10).unwrap();
+        // the second ADD V0, 1
+        trace[3].op = 0x7002;
+        assert_eq!(
+            emu().verify_trace(&trace),
+            Err(TraceMismatch::Diverged {
+                step: 3,
+                expected: TraceEntry { pc: 0x200, op: 0x7002 },
+                found: TraceEntry { pc: 0x200, op: 0x7001 },
+            })
+        );
+    }
+
+    #[test]
+    fn trace_lines_round_trip() {
+        let entry = TraceEntry { pc: 0x2A4, op: 0xD125 };
+        assert_eq!(entry.to_string(), "02A4 D125");
+        assert_eq!(TraceEntry::parse(&entry.to_string()), Some(entry));
+        assert_eq!(TraceEntry::parse("02A4"), None);
+        assert_eq!(TraceEntry::parse("02A4 D125 00"), None);
+    }
+}
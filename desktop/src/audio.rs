@@ -0,0 +1,45 @@
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use sdl2::Sdl;
+
+const BEEP_HZ: f32 = 440.0;
+const VOLUME: f32 = 0.25;
+
+pub struct SquareWave {
+    phase_inc: f32,
+    phase: f32,
+}
+
+impl AudioCallback for SquareWave {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        // high for the first half of every period, low for the second half
+        for sample in out.iter_mut() {
+            *sample = if self.phase <= 0.5 { VOLUME } else { -VOLUME };
+            self.phase = (self.phase + self.phase_inc) % 1.0;
+        }
+    }
+}
+
+// open a paused square wave device, resume it while the sound timer is running. The beep is
+// optional, so a host without a usable audio device just runs silently after one warning
+pub fn open_beeper(sdl_context: &Sdl) -> Option<AudioDevice<SquareWave>> {
+    let desired_spec = AudioSpecDesired {
+        freq: Some(44100),
+        channels: Some(1),
+        samples: None,
+    };
+    let device = sdl_context.audio().and_then(|audio_subsys| {
+        audio_subsys.open_playback(None, &desired_spec, |spec| SquareWave {
+            phase_inc: BEEP_HZ / spec.freq as f32,
+            phase: 0.0,
+        })
+    });
+    match device {
+        Ok(device) => Some(device),
+        Err(e) => {
+            println!("Warning: could not open an audio device, running without sound: {}", e);
+            None
+        }
+    }
+}
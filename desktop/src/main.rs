@@ -1,7 +1,11 @@
+mod audio;
+mod rom;
+
 use chip8_core::*;
+use rom::Strip;
 use std::env;
-use std::fs::File;
-use std::io::Read;
+use std::process;
+use std::time::Duration;
 use sdl2::event::Event;
 use sdl2::pixels::Color;
 use sdl2::rect::Rect;
@@ -12,13 +16,100 @@ use sdl2::keyboard::Keycode;
 const SCALE: u32 = 15;
 const WINDOW_WIDTH: u32 = (SCREEN_WIDTH as u32) * SCALE;
 const WINDOW_HEIGHT: u32 = (SCREEN_HEIGHT as u32)* SCALE;
-const TICK_PER_FRAME: usize = 10;
+// SUPER-CHIP hires pixels are drawn smaller so the window stays roughly the same size
+const HIRES_SCALE: u32 = 8;
+const DEFAULT_FG: Color = Color::RGB(255, 255, 255);
+const DEFAULT_BG: Color = Color::RGB(0, 0, 0);
+
+struct Options {
+    rom_path: String,
+    // keep the window and input running but skip drawing pixels, for profiling emulation cost
+    no_render: bool,
+    // trailing footer/checksum bytes to drop before loading
+    strip_trailing: Option<Strip>,
+    // print every VF flag write along with the instruction that caused it
+    log_vf: bool,
+    // auto-release keys held this many frames without a key repeat event
+    key_timeout: Option<u32>,
+    // stop the ROM if it writes into the fontset
+    protect_font: bool,
+    // draw lines between the scaled pixels to make each one distinct
+    grid: bool,
+    // CPU instructions per second
+    cpu_hz: u32,
+    // run without a window for this many seconds, then print the final screen and state hash
+    run_for: Option<Duration>,
+    // fixed seed for the CXNN random numbers
+    seed: Option<u64>,
+    // colors for lit and unlit pixels
+    fg: Color,
+    bg: Color,
+}
 
 fn main() {
     let args: Vec<_> = env::args().collect();
 
-    if args.len() != 2 {
-        println!("Usage: cargo run /path/to/game");
+    let opts = match parse_args(&args) {
+        Some(opts) => opts,
+        None => {
+            print_usage();
+            return;
+        }
+    };
+
+    // setting up chip8 core backend
+    let mut chip8 = Emu::new();
+    chip8.set_key_timeout(opts.key_timeout);
+    chip8.set_font_protection(opts.protect_font);
+    chip8.set_cpu_hz(opts.cpu_hz);
+    // --run-for output has to be reproducible, so it always runs with a fixed seed
+    let seed = if opts.run_for.is_some() { Some(opts.seed.unwrap_or(0)) } else { opts.seed };
+    if let Some(seed) = seed {
+        chip8.set_rng_seed(seed);
+    }
+    if opts.log_vf {
+        chip8.subscribe(|event| {
+            if let EmuEvent::VfWritten { cause, value } = event {
+                println!("VF = {} ({})", value, cause);
+            }
+        });
+    }
+    // load rom into buffer, from stdin if the path is "-"
+    let mut buff = match rom::read_rom(&opts.rom_path) {
+        Ok(buff) => buff,
+        Err(e) => {
+            eprintln!("Could not read {}: {}", opts.rom_path, e);
+            process::exit(1);
+        }
+    };
+    if buff.is_empty() {
+        eprintln!("Could not load {}: the ROM is empty", opts.rom_path);
+        process::exit(1);
+    }
+    match opts.strip_trailing {
+        Some(strip) => {
+            let dropped = rom::strip_trailing(&mut buff, strip);
+            // a short ROM can lose everything, which would just run zeroed memory
+            if buff.is_empty() {
+                eprintln!("Could not load {}: nothing is left after stripping {} bytes", opts.rom_path, dropped);
+                process::exit(1);
+            }
+            println!("Stripped {} trailing bytes, loading {} bytes", dropped, buff.len());
+        },
+        None => {
+            let footer = rom::detect_footer(&buff);
+            if footer > 0 {
+                println!("Note: the ROM ends with {} bytes of 0xFF padding, try --strip-trailing auto if it misbehaves", footer);
+            }
+        },
+    }
+    if let Err(e) = chip8.load(&buff) {
+        eprintln!("Could not load {}: {}", opts.rom_path, e);
+        process::exit(1);
+    }
+
+    if let Some(duration) = opts.run_for {
+        run_timed(&mut chip8, duration);
         return;
     }
 
@@ -41,17 +132,14 @@ fn main() {
     canvas.clear();
     canvas.present();
 
-    // setting up chip8 core backend
-    let mut chip8 = Emu::new();
-    // load rom
-    let mut rom = File::open(&args[1]).expect("Failed to load file");
-    let mut buff = Vec::new();
-    // load rom into buffer
-    rom.read_to_end(&mut buff).unwrap();
-    chip8.load(&buff);
+    let beeper = audio::open_beeper(&sdl_context);
 
     // setting up events
     let mut event_pump = sdl_context.event_pump().unwrap();
+    // only warn once about a ROM that never touches the screen
+    let mut blank_warned = false;
+    // P freezes the emulator, N then runs a single instruction
+    let mut paused = false;
 
     // labeled loop for the emulator
     'gameloop: loop {
@@ -60,6 +148,28 @@ fn main() {
                 Event::Quit{..} => {
                     break 'gameloop;
                 },
+                // P and N aren't part of the CHIP-8 keymap, so they are free for the debug controls
+                Event::KeyDown { keycode: Some(Keycode::P), repeat: false, .. } => {
+                    paused = !paused;
+                    println!("{}", if paused { "Paused (N to step)" } else { "Resumed" });
+                },
+                // F1 restarts the ROM from scratch without relaunching
+                Event::KeyDown { keycode: Some(Keycode::F1), repeat: false, .. } => {
+                    chip8.reset();
+                    // the same buffer loaded fine at startup, so this can't fail
+                    chip8.load(&buff).unwrap();
+                    blank_warned = false;
+                    println!("Reset");
+                },
+                Event::KeyDown { keycode: Some(Keycode::N), repeat: false, .. } if paused => {
+                    match chip8.step() {
+                        Ok(op) => println!("{}", op),
+                        Err(e) => {
+                            println!("Emulation stopped: {}", e);
+                            break 'gameloop;
+                        },
+                    }
+                },
                 Event::KeyDown { keycode: Some(key), .. } => {
                     if let Some(k) = keymap(key) {
                         chip8.keypress(k, true);
@@ -73,35 +183,186 @@ fn main() {
                 _ => ()
             }
         }
-        // clock cycle --> for loop to enhance refresh rate
-        for _ in 0..TICK_PER_FRAME {
-            chip8.tick();
+        // run one frame worth of instructions and tick the timers once, the screen is redrawn every
+        // frame anyway (even when paused) since presenting with vsync is what paces the loop
+        if !paused {
+            if let Err(e) = chip8.run_frame(chip8.ticks_per_frame()) {
+                println!("Emulation stopped: {}", e);
+                break 'gameloop;
+            }
+        }
+        if !blank_warned && chip8.display_never_initialized() {
+            println!("Warning: the ROM has run {} instructions without clearing or drawing to the screen, it may be stuck or not a valid CHIP-8 program", chip8.cycles());
+            blank_warned = true;
+        }
+        if let Some(beeper) = &beeper {
+            if chip8.frame_beeping() && !paused {
+                beeper.resume();
+            } else {
+                beeper.pause();
+            }
+        }
+        draw_screen(&chip8, &mut canvas, opts.no_render, opts.grid, opts.fg, opts.bg);
+    }
+}
+
+fn parse_args(args: &[String]) -> Option<Options> {
+    let mut rom_path = None;
+    let mut no_render = false;
+    let mut strip_trailing = None;
+    let mut log_vf = false;
+    let mut key_timeout = None;
+    let mut protect_font = false;
+    let mut grid = false;
+    let mut cpu_hz = DEFAULT_CPU_HZ;
+    let mut run_for = None;
+    let mut seed = None;
+    let mut fg = DEFAULT_FG;
+    let mut bg = DEFAULT_BG;
+
+    let mut args = args.iter().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--no-render" => no_render = true,
+            "--grid" => grid = true,
+            // try_from_secs_f64 rejects negative, NaN, infinite and too long durations
+            "--run-for" => run_for = Some(Duration::try_from_secs_f64(args.next()?.parse().ok()?).ok()?),
+            "--seed" => seed = Some(args.next()?.parse().ok()?),
+            "--fg" => fg = parse_color(args.next()?, DEFAULT_FG),
+            "--bg" => bg = parse_color(args.next()?, DEFAULT_BG),
+            // instructions per frame, converted to a rate since the core spreads it over TIMER_HZ frames
+            "--ipf" => cpu_hz = args.next()?.parse::<u32>().ok()?.checked_mul(TIMER_HZ)?,
+            "--hz" => cpu_hz = args.next()?.parse().ok()?,
+            "--log-vf" => log_vf = true,
+            "--protect-font" => protect_font = true,
+            "--key-timeout" => key_timeout = Some(args.next()?.parse().ok()?),
+            "--strip-trailing" => strip_trailing = Some(Strip::parse(args.next()?)?),
+            flag if flag.starts_with("--") => return None,
+            path if rom_path.is_none() => rom_path = Some(path.to_string()),
+            _ => return None,
+        }
+    }
+
+    Some(Options {
+        rom_path: rom_path?,
+        no_render,
+        strip_trailing,
+        log_vf,
+        key_timeout,
+        protect_font,
+        grid,
+        cpu_hz,
+        run_for,
+        seed,
+        fg,
+        bg,
+    })
+}
+
+// a quarter of the way from the background to the pixel color, so the lines show against both
+// whatever --fg/--bg are (dark gray with the default white on black)
+fn grid_color(fg: Color, bg: Color) -> Color {
+    let blend = |f: u8, b: u8| (b as i16 + (f as i16 - b as i16) / 4) as u8;
+    Color::RGB(blend(fg.r, bg.r), blend(fg.g, bg.g), blend(fg.b, bg.b))
+}
+
+// parse RRGGBB (optionally with a leading #) into a color, falling back to the default on bad input
+fn parse_color(hex: &str, default: Color) -> Color {
+    let digits = hex.strip_prefix('#').unwrap_or(hex);
+    // from_str_radix also takes a leading '+', so check the digits first
+    let valid = digits.len() == 6 && digits.chars().all(|c| c.is_ascii_hexdigit());
+    let rgb = if valid { u32::from_str_radix(digits, 16).ok() } else { None };
+    match rgb {
+        Some(rgb) => Color::RGB((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8),
+        None => {
+            println!("Warning: '{}' is not a RRGGBB color, using the default", hex);
+            default
         }
-        // add 1 to timer counter register
-        chip8.tick_timers();
-        draw_screen(&chip8, &mut canvas);
     }
 }
 
-fn draw_screen(emu: &Emu, canvas: &mut Canvas<Window>) {
-    // clear canvas --> set to black by default
-    canvas.set_draw_color(Color::RGB(0, 0, 0));
+fn print_usage() {
+    println!("Usage: cargo run /path/to/game [options]");
+    println!("       pass - as the path to read the ROM from stdin");
+    println!();
+    println!("Options:");
+    println!("  --bg RRGGBB             background color (default 000000)");
+    println!("  --fg RRGGBB             pixel color (default FFFFFF)");
+    println!("  --grid                  draw lines between pixels to tell them apart");
+    println!("  --no-render             skip drawing pixels (the screen stays blank), to profile emulation without rendering");
+    println!("  --hz N                  run the CPU at N instructions per second (rounded to the nearest whole instruction per frame, at least 1)");
+    println!("  --ipf N                 run N instructions per {}Hz frame (default {}, 0 runs 1)", TIMER_HZ, DEFAULT_CPU_HZ / TIMER_HZ);
+    println!("  --key-timeout FRAMES    release keys held this many frames without a key repeat (keep it above the OS repeat delay)");
+    println!("  --log-vf                print every VF flag write and the instruction that caused it");
+    println!("  --protect-font          stop with an error if the ROM writes into the built-in font");
+    println!("  --run-for SECONDS       run without a window for SECONDS, then print the final screen and state hash");
+    println!("  --seed N                seed the random number generator (--run-for uses 0 unless given)");
+    println!("  --strip-trailing N      drop the last N bytes of the ROM before loading, or 'auto' to drop 0xFF padding");
+}
+
+// headless time-boxed run for automated checks, the output only depends on the ROM, the duration and the seed
+fn run_timed(emu: &mut Emu, duration: Duration) {
+    let summary = emu.run_for(duration, &mut SystemClock::new());
+
+    for row in emu.get_display().chunks(emu.screen_width()) {
+        let line: String = row.iter().map(|pixel| if *pixel { '#' } else { '.' }).collect();
+        println!("{}", line);
+    }
+    println!("cycles: {}", summary.cycles);
+    println!("lit pixels: {}", summary.lit_pixels);
+    println!("state hash: {:016x}", summary.state_hash);
+    // a crashing ROM must not look like a pass to whatever is checking the output
+    if let Some(e) = summary.error {
+        eprintln!("stopped early: {}", e);
+        process::exit(1);
+    }
+}
+
+fn draw_screen(emu: &Emu, canvas: &mut Canvas<Window>, no_render: bool, grid: bool, fg: Color, bg: Color) {
+    // clear canvas --> set to the background color (black by default)
+    canvas.set_draw_color(bg);
     canvas.clear();
 
+    if no_render {
+        canvas.present();
+        return;
+    }
+
+    let width = emu.screen_width() as u32;
+    let height = emu.screen_height() as u32;
+    let scale = if emu.is_hires() { HIRES_SCALE } else { SCALE };
+    // the window follows the resolution when a ROM switches between lores and hires
+    if canvas.window().size() != (width * scale, height * scale) {
+        canvas.window_mut().set_size(width * scale, height * scale).unwrap();
+    }
+
     let screen_buff = emu.get_display();
-    // set draw color to white, draw pixel with white if the display pixel boolean is true
-    canvas.set_draw_color(Color::RGB(255, 255, 255));
+    // set draw color to the foreground color (white by default), draw pixel if the display pixel boolean is true
+    canvas.set_draw_color(fg);
     for (i, pixel) in screen_buff.iter().enumerate() {
         if *pixel {
             // convert 1D screen buffer to 2D (x, y) position
-            let x = (i % SCREEN_WIDTH) as u32;
-            let y = (i / SCREEN_WIDTH) as u32;
+            let x = i as u32 % width;
+            let y = i as u32 / width;
             // draw a rectangle at (x,y) scaled up
-            let rect = Rect::new((x * SCALE) as i32, (y * SCALE) as i32, SCALE, SCALE);
+            let rect = Rect::new((x * scale) as i32, (y * scale) as i32, scale, scale);
             canvas.fill_rect(rect).unwrap();
         }
     }
 
+    if grid {
+        // lines go on top of the pixel fills, one on every pixel boundary
+        canvas.set_draw_color(grid_color(fg, bg));
+        for x in 1..width {
+            let line_x = (x * scale) as i32;
+            canvas.draw_line((line_x, 0), (line_x, (height * scale) as i32)).unwrap();
+        }
+        for y in 1..height {
+            let line_y = (y * scale) as i32;
+            canvas.draw_line((0, line_y), ((width * scale) as i32, line_y)).unwrap();
+        }
+    }
+
     canvas.present();
 }
 
@@ -147,3 +408,79 @@ fn keymap(key: Keycode) -> Option<usize> {
         _ => None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Option<Options> {
+        let args: Vec<String> = ["desktop"].iter().chain(args).map(|a| a.to_string()).collect();
+        parse_args(&args)
+    }
+
+    #[test]
+    fn no_render_is_off_by_default() {
+        assert!(!parse(&["game.ch8"]).unwrap().no_render);
+        let opts = parse(&["game.ch8", "--no-render"]).unwrap();
+        assert!(opts.no_render);
+        assert_eq!(opts.rom_path, "game.ch8");
+    }
+
+    #[test]
+    fn grid_color_stays_visible_on_any_background() {
+        assert_eq!(grid_color(DEFAULT_FG, DEFAULT_BG), Color::RGB(63, 63, 63));
+        let bg = Color::RGB(0x40, 0x40, 0x40);
+        let grid = grid_color(DEFAULT_FG, bg);
+        assert_ne!(grid, bg);
+        assert_ne!(grid, DEFAULT_FG);
+        // dark pixels on a light background
+        let grid = grid_color(Color::RGB(0, 0, 0), Color::RGB(200, 200, 200));
+        assert_eq!(grid, Color::RGB(150, 150, 150));
+    }
+
+    #[test]
+    fn cpu_speed_flags() {
+        assert_eq!(parse(&["game.ch8"]).unwrap().cpu_hz, DEFAULT_CPU_HZ);
+        assert_eq!(parse(&["game.ch8", "--ipf", "15"]).unwrap().cpu_hz, 15 * TIMER_HZ);
+        assert_eq!(parse(&["game.ch8", "--hz", "1000"]).unwrap().cpu_hz, 1000);
+        // the last flag wins
+        assert_eq!(parse(&["game.ch8", "--hz", "1000", "--ipf", "2"]).unwrap().cpu_hz, 2 * TIMER_HZ);
+        assert!(parse(&["game.ch8", "--ipf", "fast"]).is_none());
+        assert!(parse(&["game.ch8", "--ipf"]).is_none());
+    }
+
+    #[test]
+    fn dash_reads_the_rom_from_stdin() {
+        assert_eq!(parse(&["-"]).unwrap().rom_path, rom::STDIN_PATH);
+        assert_eq!(parse(&["--grid", "-"]).unwrap().rom_path, rom::STDIN_PATH);
+        assert!(parse(&[]).is_none());
+    }
+
+    #[test]
+    fn run_for_and_seed_flags() {
+        let opts = parse(&["game.ch8"]).unwrap();
+        assert_eq!(opts.run_for, None);
+        assert_eq!(opts.seed, None);
+        let opts = parse(&["game.ch8", "--run-for", "2.5", "--seed", "9"]).unwrap();
+        assert_eq!(opts.run_for, Some(Duration::from_millis(2500)));
+        assert_eq!(opts.seed, Some(9));
+        assert!(parse(&["game.ch8", "--run-for", "-1"]).is_none());
+        assert!(parse(&["game.ch8", "--run-for", "inf"]).is_none());
+        assert!(parse(&["game.ch8", "--run-for", "NaN"]).is_none());
+        // too long for a Duration
+        assert!(parse(&["game.ch8", "--run-for", "1e20"]).is_none());
+    }
+
+    #[test]
+    fn colors_parse_as_hex_or_fall_back() {
+        assert_eq!(parse_color("FFB000", DEFAULT_FG), Color::RGB(0xFF, 0xB0, 0x00));
+        assert_eq!(parse_color("#282828", DEFAULT_BG), Color::RGB(0x28, 0x28, 0x28));
+        assert_eq!(parse_color("amber", DEFAULT_FG), DEFAULT_FG);
+        assert_eq!(parse_color("FFF", DEFAULT_BG), DEFAULT_BG);
+        assert_eq!(parse_color("+FFFFF", DEFAULT_BG), DEFAULT_BG);
+
+        let opts = parse(&["game.ch8", "--fg", "00FF00", "--bg", "nope"]).unwrap();
+        assert_eq!(opts.fg, Color::RGB(0, 0xFF, 0));
+        assert_eq!(opts.bg, DEFAULT_BG);
+    }
+}
@@ -0,0 +1,100 @@
+// helpers for getting ROM bytes ready before they are handed to the core
+
+use std::fs::File;
+use std::io::{self, Read};
+
+// passing this instead of a path reads the ROM from stdin
+pub const STDIN_PATH: &str = "-";
+
+// a run of at least this many 0xFF bytes at the end of a ROM is treated as padding
+// (shorter runs are common in real sprite data, e.g. a solid bar)
+const PADDING_MIN_RUN: usize = 16;
+
+// read the whole ROM from a file, or from stdin when the path is "-"
+pub fn read_rom(path: &str) -> io::Result<Vec<u8>> {
+    let mut buff = Vec::new();
+    if path == STDIN_PATH {
+        io::stdin().read_to_end(&mut buff)?;
+    } else {
+        File::open(path)?.read_to_end(&mut buff)?;
+    }
+    Ok(buff)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strip {
+    // drop exactly this many bytes from the end
+    Bytes(usize),
+    // drop whatever detect_footer finds
+    Auto,
+}
+
+impl Strip {
+    pub fn parse(value: &str) -> Option<Strip> {
+        if value == "auto" {
+            Some(Strip::Auto)
+        } else {
+            value.parse().ok().map(Strip::Bytes)
+        }
+    }
+}
+
+// guess how many trailing bytes are not part of the program, dumps taken from EPROMs or
+// fixed-size images are usually padded with 0xFF, which would otherwise land in RAM
+pub fn detect_footer(buff: &[u8]) -> usize {
+    let run = buff.iter().rev().take_while(|b| **b == 0xFF).count();
+    if run >= PADDING_MIN_RUN {
+        run
+    } else {
+        0
+    }
+}
+
+// remove the trailing bytes and return how many were dropped
+pub fn strip_trailing(buff: &mut Vec<u8>, strip: Strip) -> usize {
+    let count = match strip {
+        Strip::Bytes(n) => n.min(buff.len()),
+        Strip::Auto => detect_footer(buff),
+    };
+    buff.truncate(buff.len() - count);
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_a_fixed_number_of_bytes() {
+        let mut buff = vec![0x12, 0x00, 0xDE, 0xAD, 0xBE, 0xEF];
+        assert_eq!(strip_trailing(&mut buff, Strip::Bytes(4)), 4);
+        assert_eq!(buff, vec![0x12, 0x00]);
+    }
+
+    #[test]
+    fn strip_never_drops_more_than_the_rom() {
+        let mut buff = vec![0x12, 0x00];
+        assert_eq!(strip_trailing(&mut buff, Strip::Bytes(999)), 2);
+        assert!(buff.is_empty());
+    }
+
+    #[test]
+    fn auto_strip_drops_long_padding_only() {
+        let mut buff = vec![0x12, 0x00];
+        buff.extend([0xFF; PADDING_MIN_RUN]);
+        assert_eq!(strip_trailing(&mut buff, Strip::Auto), PADDING_MIN_RUN);
+        assert_eq!(buff.len(), 2);
+
+        // a short run of 0xFF is probably sprite data
+        let mut buff = vec![0x12, 0x00, 0xFF, 0xFF];
+        assert_eq!(strip_trailing(&mut buff, Strip::Auto), 0);
+        assert_eq!(buff.len(), 4);
+    }
+
+    #[test]
+    fn parses_strip_values() {
+        assert_eq!(Strip::parse("auto"), Some(Strip::Auto));
+        assert_eq!(Strip::parse("3"), Some(Strip::Bytes(3)));
+        assert_eq!(Strip::parse("x"), None);
+    }
+}